@@ -0,0 +1,612 @@
+use std::fmt;
+
+/// A Source engine cfg file kept line by line, so that editing one cvar leaves
+/// comments, ordering, unknown cvars and commands such as `exec` untouched.
+#[derive(Debug, Clone, Default)]
+pub struct CfgDocument {
+    lines: Vec<Line>,
+    trailing_newline: bool,
+    /// Whether lines end in `\r\n`, judged by the first one; added lines follow it
+    crlf: bool,
+    errors: Vec<SyntaxError>,
+}
+
+#[derive(Debug, Clone)]
+struct Line {
+    /// Original text without the line ending; None once the line has been modified
+    raw: Option<String>,
+    /// Whether the line ends in `\r\n` rather than `\n`
+    crlf: bool,
+    indent: String,
+    commands: Vec<Command>,
+    /// Trailing `//` comment, including the slashes
+    comment: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+struct Command {
+    name: String,
+    value: String,
+    /// Original text of this command within its line
+    raw: Option<String>,
+}
+
+/// A syntax error in a cfg file, with 1-based line and column.
+#[derive(Debug, Clone)]
+pub struct SyntaxError {
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl fmt::Display for SyntaxError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}, column {}: {}", self.line, self.column, self.message)
+    }
+}
+
+impl std::error::Error for SyntaxError {}
+
+// Commands that may appear in a cfg file but are not settings
+const COMMANDS: &[&str] = &[
+    "alias", "bind", "bot_add", "bot_add_ct", "bot_add_t", "bot_kick", "echo", "exec",
+    "execifexists", "host_writeconfig", "kick", "log", "say", "unbind", "writeid", "writeip",
+];
+
+impl CfgDocument {
+    /// Parses a cfg file. Lines with syntax errors are kept verbatim and
+    /// reported through `errors`, the way the game skips them.
+    pub fn parse(content: &str) -> Self {
+        let trailing_newline = content.ends_with('\n');
+        let body = content.strip_suffix('\n').unwrap_or(content);
+
+        let mut document = Self {
+            trailing_newline,
+            crlf: body
+                .split('\n')
+                .next()
+                .is_some_and(|line| line.ends_with('\r')),
+            ..Self::default()
+        };
+        if content.is_empty() {
+            return document;
+        }
+
+        for (index, raw) in body.split('\n').enumerate() {
+            let (raw, crlf) = match raw.strip_suffix('\r') {
+                Some(raw) => (raw, true),
+                None => (raw, false),
+            };
+            let mut line = match tokenize_line(raw, index + 1) {
+                Ok(parsed) => Line::from_parsed(raw, parsed),
+                Err(e) => {
+                    document.errors.push(e);
+                    Line::verbatim(raw)
+                }
+            };
+            line.crlf = crlf;
+            document.lines.push(line);
+        }
+        document
+    }
+
+    pub fn errors(&self) -> &[SyntaxError] {
+        &self.errors
+    }
+
+    /// Returns the effective value of a cvar, i.e. the last assignment in the file.
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.commands()
+            .filter(|command| command.is(key))
+            .last()
+            .map(|command| command.value.as_str())
+    }
+
+    /// Sets a cvar, replacing its last assignment in place or appending it.
+    pub fn set(&mut self, key: &str, new_value: &str) {
+        let existing = self.lines.iter_mut().rev().find_map(|line| {
+            let index = line.commands.iter().rposition(|command| command.is(key))?;
+            Some((line, index))
+        });
+
+        match existing {
+            Some((line, index)) => {
+                let command = &mut line.commands[index];
+                if command.value != new_value {
+                    command.value = new_value.to_string();
+                    command.raw = None;
+                    line.raw = None;
+                }
+            }
+            None => self.lines.push(Line {
+                raw: None,
+                crlf: self.crlf,
+                indent: String::new(),
+                commands: vec![Command {
+                    name: key.to_string(),
+                    value: new_value.to_string(),
+                    raw: None,
+                }],
+                comment: None,
+            }),
+        }
+    }
+
+    /// Removes every assignment of a cvar. Returns false if it was not set.
+    pub fn unset(&mut self, key: &str) -> bool {
+        let mut removed = false;
+        for line in &mut self.lines {
+            let before = line.commands.len();
+            line.commands.retain(|command| !command.is(key));
+            if line.commands.len() != before {
+                line.raw = None;
+                removed = true;
+            }
+        }
+
+        // Drop lines that held nothing but the removed cvar
+        self.lines
+            .retain(|line| line.raw.is_some() || !line.commands.is_empty() || line.comment.is_some());
+        removed
+    }
+
+    /// Effective cvar values in file order, excluding commands like `exec`.
+    pub fn cvars(&self) -> Vec<(String, String)> {
+        let mut cvars: Vec<(String, String)> = vec![];
+        for command in self.commands() {
+            if is_command(&command.name) {
+                continue;
+            }
+            match cvars
+                .iter_mut()
+                .find(|(key, _)| key.eq_ignore_ascii_case(&command.name))
+            {
+                Some(entry) => entry.1 = command.value.clone(),
+                None => cvars.push((command.name.clone(), command.value.clone())),
+            }
+        }
+        cvars
+    }
+
+    pub fn render(&self) -> String {
+        let mut output = self
+            .lines
+            .iter()
+            .map(Line::render)
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        if self.trailing_newline {
+            output.push('\n');
+        } else if self.lines_modified() {
+            // The last line had no ending, it gets one like the others
+            if self.crlf && !self.lines.last().is_some_and(|line| line.crlf) {
+                output.push('\r');
+            }
+            output.push('\n');
+        }
+        output
+    }
+
+    /// Every cvar assignment with its 1-based line number, overridden ones included.
+    pub fn assignments(&self) -> Vec<(usize, &str, &str)> {
+        self.lines
+            .iter()
+            .enumerate()
+            .flat_map(|(index, line)| {
+                line.commands
+                    .iter()
+                    .filter(|command| !is_command(&command.name))
+                    .map(move |command| (index + 1, command.name.as_str(), command.value.as_str()))
+            })
+            .collect()
+    }
+
+    fn commands(&self) -> impl Iterator<Item = &Command> {
+        self.lines.iter().flat_map(|line| line.commands.iter())
+    }
+
+    fn lines_modified(&self) -> bool {
+        self.lines.iter().any(|line| line.raw.is_none())
+    }
+}
+
+impl Line {
+    fn verbatim(raw: &str) -> Self {
+        Self {
+            raw: Some(raw.to_string()),
+            crlf: false,
+            indent: String::new(),
+            commands: vec![],
+            comment: None,
+        }
+    }
+
+    fn from_parsed(raw: &str, parsed: ParsedLine) -> Self {
+        let indent_len = raw.len() - raw.trim_start().len();
+        Self {
+            raw: Some(raw.to_string()),
+            crlf: false,
+            indent: raw[..indent_len].to_string(),
+            commands: parsed
+                .commands
+                .into_iter()
+                .map(|(tokens, span)| Command::from_tokens(tokens, &raw[span]))
+                .collect(),
+            comment: parsed.comment.map(|start| raw[start..].to_string()),
+        }
+    }
+
+    fn render(&self) -> String {
+        let mut line = self.render_text();
+        if self.crlf {
+            line.push('\r');
+        }
+        line
+    }
+
+    fn render_text(&self) -> String {
+        if let Some(raw) = &self.raw {
+            return raw.clone();
+        }
+
+        let mut line = self.indent.clone();
+        let commands: Vec<String> = self.commands.iter().map(Command::render).collect();
+        line.push_str(&commands.join("; "));
+        if let Some(comment) = &self.comment {
+            if !commands.is_empty() {
+                line.push(' ');
+            }
+            line.push_str(comment);
+        }
+        line
+    }
+}
+
+impl Command {
+    fn from_tokens(tokens: Vec<Token>, raw: &str) -> Self {
+        let mut tokens = tokens.into_iter();
+        let name = tokens.next().map(|token| token.text).unwrap_or_default();
+        let args: Vec<Token> = tokens.collect();
+
+        // Like the engine, a single argument is taken as is, several are
+        // joined back together so that unquoted values may contain spaces
+        let value = match args.as_slice() {
+            [] => String::new(),
+            [arg] => arg.text.clone(),
+            [first, .., last] => raw[first.start..last.end].to_string(),
+        };
+
+        Self {
+            name,
+            value,
+            raw: Some(raw.to_string()),
+        }
+    }
+
+    fn is(&self, key: &str) -> bool {
+        self.name.eq_ignore_ascii_case(key)
+    }
+
+    fn render(&self) -> String {
+        match &self.raw {
+            Some(raw) => raw.clone(),
+            None => format!("{} {}", self.name, quote(&self.value)),
+        }
+    }
+}
+
+pub fn is_command(name: &str) -> bool {
+    COMMANDS.iter().any(|command| command.eq_ignore_ascii_case(name))
+}
+
+fn quote(value: &str) -> String {
+    if !value.is_empty() && value.parse::<f64>().is_ok() {
+        value.to_string()
+    } else {
+        format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+    }
+}
+
+/// A word of a command; `start` and `end` are byte offsets of its source
+/// text within the command, quotes included.
+#[derive(Debug)]
+struct Token {
+    text: String,
+    start: usize,
+    end: usize,
+}
+
+#[derive(Debug, Default)]
+struct ParsedLine {
+    /// Tokens of each `;`-separated command and the command's byte span
+    commands: Vec<(Vec<Token>, std::ops::Range<usize>)>,
+    /// Byte offset of a trailing `//` comment
+    comment: Option<usize>,
+}
+
+/// Splits one line of a cfg file into commands following the engine's rules:
+/// `//` starts a comment, `;` separates commands, and double quotes group
+/// words. Inside quotes `\"` and `\\` are escapes; other backslashes are
+/// literal so that Windows paths survive.
+fn tokenize_line(line: &str, line_number: usize) -> Result<ParsedLine, SyntaxError> {
+    let error = |offset: usize, message: &str| SyntaxError {
+        line: line_number,
+        column: line[..offset].chars().count() + 1,
+        message: message.to_string(),
+    };
+
+    let mut parsed = ParsedLine::default();
+    let mut chars = line.char_indices().peekable();
+    let mut command_start: Option<usize> = None;
+    let mut tokens: Vec<Token> = vec![];
+    let mut command_end = 0;
+
+    let finish = |parsed: &mut ParsedLine, tokens: &mut Vec<Token>, start: Option<usize>, end| {
+        if let Some(start) = start {
+            // Token offsets become relative to the command's own text
+            let tokens = std::mem::take(tokens)
+                .into_iter()
+                .map(|token| Token {
+                    start: token.start - start,
+                    end: token.end - start,
+                    ..token
+                })
+                .collect();
+            parsed.commands.push((tokens, start..end));
+        }
+    };
+
+    while let Some(&(offset, c)) = chars.peek() {
+        match c {
+            c if c.is_whitespace() => {
+                chars.next();
+            }
+            ';' => {
+                chars.next();
+                finish(&mut parsed, &mut tokens, command_start.take(), command_end);
+            }
+            '/' if line[offset..].starts_with("//") => {
+                parsed.comment = Some(offset);
+                break;
+            }
+            '"' => {
+                chars.next();
+                let mut text = String::new();
+                let end = loop {
+                    match chars.next() {
+                        Some((_, '\\')) if matches!(chars.peek(), Some((_, '"' | '\\'))) => {
+                            text.push(chars.next().map(|(_, c)| c).unwrap_or_default());
+                        }
+                        Some((end, '"')) => break end + 1,
+                        Some((_, c)) => text.push(c),
+                        None => return Err(error(offset, "unterminated quoted string")),
+                    }
+                };
+                command_start.get_or_insert(offset);
+                command_end = end;
+                tokens.push(Token {
+                    text,
+                    start: offset,
+                    end,
+                });
+            }
+            _ => {
+                let mut end = offset;
+                while let Some(&(next, c)) = chars.peek() {
+                    if c.is_whitespace() || c == ';' || c == '"' || line[next..].starts_with("//") {
+                        break;
+                    }
+                    chars.next();
+                    end = next + c.len_utf8();
+                }
+                if tokens.is_empty() && !is_valid_name(&line[offset..end]) {
+                    return Err(error(offset, "invalid command name"));
+                }
+                command_start.get_or_insert(offset);
+                command_end = end;
+                tokens.push(Token {
+                    text: line[offset..end].to_string(),
+                    start: offset,
+                    end,
+                });
+            }
+        }
+    }
+    finish(&mut parsed, &mut tokens, command_start, command_end);
+
+    Ok(parsed)
+}
+
+fn is_valid_name(name: &str) -> bool {
+    name.chars()
+        .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '+' | '-' | '.'))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CORPUS: &[(&str, &str)] = &[
+        ("server.cfg", include_str!("../tests/corpus/server.cfg")),
+        (
+            "gamemode_competitive.cfg",
+            include_str!("../tests/corpus/gamemode_competitive.cfg"),
+        ),
+        (
+            "gamemode_casual.cfg",
+            include_str!("../tests/corpus/gamemode_casual.cfg"),
+        ),
+        (
+            "server_crlf.cfg",
+            include_str!("../tests/corpus/server_crlf.cfg"),
+        ),
+    ];
+
+    #[test]
+    fn corpus_parses_without_errors() {
+        for (name, content) in CORPUS {
+            let document = CfgDocument::parse(content);
+            assert!(
+                document.errors().is_empty(),
+                "{}: {:?}",
+                name,
+                document.errors()
+            );
+        }
+    }
+
+    #[test]
+    fn unmodified_corpus_renders_byte_identical() {
+        for (name, content) in CORPUS {
+            assert_eq!(CfgDocument::parse(content).render(), *content, "{}", name);
+        }
+    }
+
+    #[test]
+    fn reads_server_cfg() {
+        let document = CfgDocument::parse(CORPUS[0].1);
+        assert_eq!(
+            document.get("hostname"),
+            Some("[EU] Retakes #1 | 128 tick \"no AWP\"")
+        );
+        assert_eq!(document.get("sv_password"), Some(""));
+        assert_eq!(document.get("sv_minrate"), Some("196608"));
+        assert_eq!(
+            document.get("sv_downloadurl"),
+            Some("https://fastdl.example.com/csgo/")
+        );
+        assert_eq!(document.get("SV_LOGFILE"), Some("1"));
+        assert_eq!(document.get("bot_quota"), Some("0"));
+
+        let cvars = document.cvars();
+        assert!(cvars.iter().all(|(key, _)| !is_command(key)));
+        assert!(cvars
+            .iter()
+            .any(|(key, value)| key == "sv_region" && value == "3"));
+    }
+
+    #[test]
+    fn reads_gamemode_cfgs() {
+        let competitive = CfgDocument::parse(CORPUS[1].1);
+        assert_eq!(competitive.get("bot_difficulty"), Some("1"));
+        assert_eq!(competitive.get("bot_quota_mode"), Some("fill"));
+        assert_eq!(competitive.get("mp_roundtime"), Some("1.92"));
+        assert_eq!(
+            competitive.get("cash_team_loser_bonus_consecutive_rounds"),
+            Some("500")
+        );
+        assert_eq!(competitive.cvars().len(), 32);
+
+        let casual = CfgDocument::parse(CORPUS[2].1);
+        assert_eq!(casual.get("mp_limitteams"), Some("2"));
+        assert_eq!(casual.get("mp_teamname_1"), Some("Counter-Terrorists"));
+    }
+
+    #[test]
+    fn splits_commands_on_semicolons_outside_quotes() {
+        let document = CfgDocument::parse("a 1; b 2;c \"x;y\" ;; d\n");
+        assert_eq!(document.get("a"), Some("1"));
+        assert_eq!(document.get("b"), Some("2"));
+        assert_eq!(document.get("c"), Some("x;y"));
+        assert_eq!(document.get("d"), Some(""));
+    }
+
+    #[test]
+    fn comments_end_unquoted_words_only() {
+        let document = CfgDocument::parse(
+            "// full line\nurl http://example.com\nquoted \"http://example.com\" // note\n",
+        );
+        assert_eq!(document.get("url"), Some("http:"));
+        assert_eq!(document.get("quoted"), Some("http://example.com"));
+        assert_eq!(document.assignments().len(), 2);
+    }
+
+    #[test]
+    fn quoting_rules() {
+        let document = CfgDocument::parse(
+            "a \"say \\\"hi\\\"\"\nb \"C:\\maps\\de_x\"\nc \"back\\\\slash\"\nd two words\ne \"\"\n",
+        );
+        assert_eq!(document.get("a"), Some("say \"hi\""));
+        assert_eq!(document.get("b"), Some("C:\\maps\\de_x"));
+        assert_eq!(document.get("c"), Some("back\\slash"));
+        assert_eq!(document.get("d"), Some("two words"));
+        assert_eq!(document.get("e"), Some(""));
+    }
+
+    #[test]
+    fn reports_error_positions() {
+        let document = CfgDocument::parse("sv_lan 0\n\thostname \"open\nmp_maxrounds 24\n");
+        let errors = document.errors();
+        assert_eq!(errors.len(), 1);
+        assert_eq!((errors[0].line, errors[0].column), (2, 11));
+        assert_eq!(errors[0].message, "unterminated quoted string");
+        // The broken line is skipped, the rest still applies
+        assert_eq!(document.get("hostname"), None);
+        assert_eq!(document.get("mp_maxrounds"), Some("24"));
+
+        // Columns count characters, not bytes
+        let document = CfgDocument::parse("a \"é\"; @b 1\n");
+        let errors = document.errors();
+        assert_eq!((errors[0].line, errors[0].column), (1, 8));
+        assert_eq!(errors[0].message, "invalid command name");
+        assert_eq!(
+            errors[0].to_string(),
+            "line 1, column 8: invalid command name"
+        );
+    }
+
+    #[test]
+    fn editing_keeps_other_lines() {
+        let content = CORPUS[0].1;
+        let mut document = CfgDocument::parse(content);
+        document.set("sv_minrate", "128000");
+        document.set("mp_maxrounds", "24");
+        document.set("sv_hibernate_when_empty", "0");
+
+        let rendered = document.render();
+        let expected = content
+            .replace(
+                "sv_maxrate 0; sv_minrate 196608",
+                "sv_maxrate 0; sv_minrate 128000",
+            )
+            .replace("mp_maxrounds 30", "mp_maxrounds 24")
+            + "sv_hibernate_when_empty 0\n";
+        assert_eq!(rendered, expected);
+    }
+
+    #[test]
+    fn unset_drops_emptied_lines() {
+        let mut document = CfgDocument::parse("a 1\nb 2; c 3\n// keep\n");
+        assert!(document.unset("A"));
+        assert!(document.unset("b"));
+        assert!(!document.unset("missing"));
+        assert_eq!(document.render(), "c 3\n// keep\n");
+    }
+
+    #[test]
+    fn edited_lines_keep_crlf_endings() {
+        let content = CORPUS[3].1;
+        let mut document = CfgDocument::parse(content);
+        document.set("hostname", "Renamed");
+        document.set("sv_cheats", "0");
+
+        let rendered = document.render();
+        assert_eq!(
+            rendered,
+            content.replace("\"Windows box\"", "\"Renamed\"") + "sv_cheats 0\r\n"
+        );
+        assert_eq!(
+            rendered.matches('\n').count(),
+            rendered.matches("\r\n").count()
+        );
+    }
+
+    #[test]
+    fn crlf_without_final_newline() {
+        let mut document = CfgDocument::parse("a 1\r\nb 2");
+        assert_eq!(document.render(), "a 1\r\nb 2");
+        document.set("a", "3");
+        assert_eq!(document.render(), "a 3\r\nb 2\r\n");
+    }
+}
@@ -1,11 +1,81 @@
-use crate::config::{Config, ServerConfig};
-use crate::server::ServerManager;
+use crate::config::{Config, InstanceConfig, ServerConfig, TOKEN_CVAR};
+use crate::console::Console;
+use crate::cvars::{self, Severity};
+use crate::gamemode::GameMode;
+use crate::manifest::{AppManifest, BuildCache, FileSnapshot};
+use crate::output::OutputFormat;
+use crate::overlay;
+use crate::profile::{self, ConfigLayer, InstanceOverrides};
+use crate::progress::{format_bytes, format_duration};
+use crate::query::{PlayerInfo, QueryClient, ServerInfo};
+use crate::rcon::{RconClient, RconTarget};
+use crate::server::{ServerManager, ServerStatus, StopOptions, StopOutcome};
 use crate::steam::SteamManager;
+use crate::steamcmd::{SteamBranch, SteamLogin};
 use anyhow::{Context, Result};
+use chrono::Utc;
 use log::{error, info, warn};
-use std::path::Path;
+use serde::Serialize;
+use serde_json::json;
+use std::collections::{BTreeMap, HashMap};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+const PROTECTED_MASK: &str = "********";
+/// How long `status` trusts the last known latest build
+const BUILD_CACHE_TTL: chrono::Duration = chrono::Duration::hours(1);
+/// How long `status` waits for Steam before going with the cached build
+const BUILD_CHECK_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(20);
+
+#[derive(Serialize)]
+struct StatusEntry {
+    name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    status: Option<ServerStatus>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    players: Option<Vec<PlayerInfo>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+/// Steam login and branch selection for install and update.
+#[derive(Clone, Default)]
+pub struct DownloadOptions {
+    pub steam_user: Option<String>,
+    /// Branch to switch the instance to, `public` to leave a beta
+    pub branch: Option<String>,
+    pub branch_password: Option<String>,
+}
+
+impl DownloadOptions {
+    /// Records the branch selection in the instance metadata. Returns
+    /// whether anything changed.
+    fn apply(&self, instance: &mut InstanceConfig) -> bool {
+        if self.branch.is_none() && self.branch_password.is_none() {
+            return false;
+        }
+        if let Some(branch) = &self.branch {
+            instance.set_branch(Some(branch.clone()));
+            rebase_overlay(instance);
+            // A password only unlocks the branch it was given for
+            instance.branch_password = None;
+        }
+        if let Some(password) = &self.branch_password {
+            instance.branch_password = Some(password.clone());
+        }
+        instance.touch();
+        true
+    }
+}
 
-pub async fn install_server(name: &str, dir: Option<&Path>) -> Result<()> {
+pub async fn install_server(
+    name: &str,
+    dir: Option<&Path>,
+    mode: Option<GameMode>,
+    options: &DownloadOptions,
+    standalone: bool,
+    output: OutputFormat,
+) -> Result<()> {
     info!("Installing CS2 server: {}", name);
 
     let base_dir = dir.unwrap_or_else(|| Path::new("./servers"));
@@ -16,167 +86,1617 @@ pub async fn install_server(name: &str, dir: Option<&Path>) -> Result<()> {
         .with_context(|| format!("Failed to create server directory: {:?}", server_dir))?;
 
     // Initialize Steam manager and download CS2 server files
-    let steam_manager = SteamManager::new()?;
-    if let Err(e) = steam_manager.download_cs2_server(&server_dir).await {
-        error!("Failed to download CS2 server files: {}", e);
-        return Err(e);
-    }
+    let login = SteamManager::steam_login(options.steam_user.as_deref())?;
+    let branch = SteamBranch::new(options.branch.as_deref(), options.branch_password.as_deref());
+    let steam_manager = SteamManager::new()?.with_output(output);
+    // Check before the download, hard links fail only once it has finished
+    let shared_base = SteamManager::base_dir(&branch.name);
+    let standalone = if !standalone && !overlay::same_filesystem(&shared_base, &server_dir) {
+        warn!(
+            "The shared base {:?} is on another filesystem than {:?}, installing a standalone copy instead",
+            shared_base, server_dir
+        );
+        true
+    } else {
+        standalone
+    };
+    let base = if standalone {
+        steam_manager
+            .download_cs2_server(&server_dir, login, &branch)
+            .await
+            .map(|_| None)
+    } else {
+        create_overlay(&steam_manager, login, &branch, &server_dir)
+            .await
+            .map(Some)
+    };
+    let base = match base {
+        Ok(base) => base,
+        Err(e) => {
+            error!("Failed to download CS2 server files: {}", e);
+            return Err(e);
+        }
+    };
 
     // Generate default server configuration
-    let server_config = ServerConfig::default();
+    let mut server_config = ServerConfig::default();
+    if let Some(mode) = mode {
+        server_config.apply_mode(mode)?;
+    }
     if let Err(e) = server_config.save(&server_dir.join("server.cfg")) {
         error!("Failed to save server configuration: {}", e);
         return Err(e);
     }
 
-    // Save server metadata
-    let mut config = Config::load_or_default()?;
-    config.add_server(name.to_string(), server_dir.clone())?;
-    if let Err(e) = config.save() {
-        error!("Failed to save tool configuration: {}", e);
-        return Err(e);
+    // Save server metadata
+    let mut config = Config::load_or_default()?;
+    config.add_server(name.to_string(), server_dir.clone())?;
+    let instance = config.get_instance_mut(name)?;
+    options.apply(instance);
+    instance.base = base.clone();
+    if let Err(e) = config.save() {
+        error!("Failed to save tool configuration: {}", e);
+        return Err(e);
+    }
+
+    info!(
+        "CS2 server '{}' installed successfully at {:?}",
+        name, server_dir
+    );
+    if output.is_machine() {
+        output.emit(
+            "install",
+            &json!({
+                "name": name,
+                "path": server_dir,
+                "mode": server_config.game_mode(),
+                "base": base,
+            }),
+        )?;
+    } else {
+        println!(
+            "CS2 server '{}' installed successfully at {:?}",
+            name, server_dir
+        );
+    }
+    Ok(())
+}
+
+/// Links a new instance to the shared base install of its branch.
+async fn create_overlay(
+    steam_manager: &SteamManager,
+    login: SteamLogin,
+    branch: &SteamBranch,
+    server_dir: &Path,
+) -> Result<PathBuf> {
+    let base = steam_manager.ensure_base(login, branch).await?;
+    let linked = overlay::sync(&base, server_dir)?;
+    info!("Linked {} files from the shared base {:?}", linked, base);
+    Ok(base)
+}
+
+/// Moves an overlay to the shared base install of its current branch.
+fn rebase_overlay(instance: &mut InstanceConfig) {
+    if instance.base.is_some() {
+        instance.base = Some(SteamManager::base_dir(instance.branch_name()));
+    }
+}
+
+pub async fn start_server(
+    name: &str,
+    foreground: bool,
+    dry_run: bool,
+    output: OutputFormat,
+) -> Result<()> {
+    let config = Config::load_or_default()?;
+    let instance = config.get_instance(name)?;
+    let server_path = &instance.path;
+
+    let mut server_manager = ServerManager::new(server_path.clone());
+    if dry_run {
+        let launch = server_manager.launch_command(instance)?;
+        if output.is_machine() {
+            let env: serde_json::Map<String, serde_json::Value> = launch
+                .env
+                .iter()
+                .map(|(key, value)| (key.clone(), json!(value)))
+                .collect();
+            output.emit(
+                "start_dry_run",
+                &json!({
+                    "name": name,
+                    "executable": launch.executable,
+                    "working_dir": launch.working_dir,
+                    "args": launch.display_args(),
+                    "env": env,
+                }),
+            )?;
+        } else {
+            println!("Working directory: {}", launch.working_dir.display());
+            for (key, value) in &launch.env {
+                println!("Environment: {}={}", key, value);
+            }
+            println!("{}", launch.command_line());
+        }
+        return Ok(());
+    }
+
+    info!("Starting server: {}", name);
+    if foreground && !output.is_machine() {
+        println!("Running server '{}' in the foreground, press Ctrl+C to stop", name);
+    }
+    if let Err(e) = server_manager.start(instance, !foreground).await {
+        error!("Failed to start server '{}': {}", name, e);
+        return Err(e);
+    }
+
+    if output.is_machine() {
+        output.emit(
+            "start",
+            &json!({ "name": name, "foreground": foreground, "log_dir": server_path.join("logs") }),
+        )?;
+    } else if foreground {
+        info!("Server '{}' exited", name);
+        println!("Server '{}' exited", name);
+    } else {
+        info!("Server '{}' started successfully", name);
+        println!(
+            "Server '{}' started successfully, console output is logged to {:?}",
+            name,
+            server_path.join("logs")
+        );
+    }
+    Ok(())
+}
+
+pub async fn stop_server(name: &str, options: &StopOptions, output: OutputFormat) -> Result<()> {
+    info!("Stopping server: {}", name);
+
+    let config = Config::load_or_default()?;
+    let server_path = config.get_server_path(name)?;
+
+    let mut server_manager = ServerManager::new(server_path.clone());
+    if let (Some(countdown), false) = (options.countdown, output.is_machine()) {
+        println!("Announcing shutdown of '{}' in {} seconds...", name, countdown);
+    }
+    let report = match server_manager.stop(options).await {
+        Ok(report) => report,
+        Err(e) => {
+            error!("Failed to stop server '{}': {}", name, e);
+            return Err(e);
+        }
+    };
+
+    info!("Server '{}' stop outcome: {:?}", name, report.outcome);
+    if output.is_machine() {
+        return output.emit(
+            "stop",
+            &json!({
+                "name": name,
+                "outcome": report.outcome,
+                "countdown_skipped": report.countdown_skipped,
+            }),
+        );
+    }
+    if report.countdown_skipped {
+        println!(
+            "Warning: {}: RCON is unavailable, the shutdown countdown was not announced",
+            name
+        );
+    }
+    match report.outcome {
+        StopOutcome::NotRunning => println!("Server '{}' is not running", name),
+        StopOutcome::Quit => println!("Server '{}' stopped cleanly", name),
+        StopOutcome::Terminated => println!("Server '{}' stopped after SIGTERM", name),
+        StopOutcome::Killed => println!(
+            "Server '{}' did not shut down within {} seconds and was killed",
+            name,
+            options.timeout.as_secs()
+        ),
+    }
+    Ok(())
+}
+
+pub async fn server_status(name: Option<&str>, output: OutputFormat) -> Result<()> {
+    info!("Checking server status");
+
+    let config = Config::load_or_default()?;
+
+    if let Some(name) = name {
+        let instance = config.get_instance(name)?;
+        let mut server_manager = ServerManager::new(instance.path.clone());
+        let status = match server_manager.get_status(instance).await {
+            Ok(mut status) => {
+                check_needs_update(&mut status, instance, &mut HashMap::new()).await;
+                status
+            }
+            Err(e) => {
+                error!("Failed to get status for server '{}': {}", name, e);
+                return Err(e);
+            }
+        };
+        info!("Server '{}' status: {:?}", name, status.state);
+
+        let players = if status.info.is_some() {
+            match server_manager.query_players().await {
+                Ok(players) => Some(players),
+                Err(e) => {
+                    warn!("Failed to query players for server '{}': {}", name, e);
+                    None
+                }
+            }
+        } else {
+            None
+        };
+
+        if output.is_machine() {
+            let entry = StatusEntry {
+                name: name.to_string(),
+                status: Some(status),
+                players,
+                error: None,
+            };
+            return output.emit("status", &json!({ "servers": [entry] }));
+        }
+
+        print_status_table(&[(name.to_string(), Ok(status.clone()))]);
+        if let Some(server_info) = &status.info {
+            println!();
+            print_server_info(server_info);
+            print_players(players.as_deref().unwrap_or_default());
+        }
+        if let Some(line) = &status.last_log_line {
+            println!();
+            println!("Last log line: {}", line);
+        }
+        print_status_warnings(&[(name.to_string(), Ok(status))]);
+    } else {
+        let mut statuses = vec![];
+        let mut latest_builds = HashMap::new();
+        for server_name in config.list_servers() {
+            let instance = config.get_instance(&server_name)?;
+            let mut server_manager = ServerManager::new(instance.path.clone());
+            let mut status = server_manager.get_status(instance).await;
+            if let Ok(status) = &mut status {
+                check_needs_update(status, instance, &mut latest_builds).await;
+            }
+            match &status {
+                Ok(status) => info!("Server '{}' status: {:?}", server_name, status.state),
+                Err(e) => warn!("Failed to get status for server '{}': {}", server_name, e),
+            }
+            statuses.push((server_name, status));
+        }
+
+        if output.is_machine() {
+            let entries: Vec<StatusEntry> = statuses
+                .into_iter()
+                .map(|(name, status)| match status {
+                    Ok(status) => StatusEntry {
+                        name,
+                        status: Some(status),
+                        players: None,
+                        error: None,
+                    },
+                    Err(e) => StatusEntry {
+                        name,
+                        status: None,
+                        players: None,
+                        error: Some(format!("{:#}", e)),
+                    },
+                })
+                .collect();
+            return output.emit("status", &json!({ "servers": entries }));
+        }
+
+        print_status_table(&statuses);
+        print_status_warnings(&statuses);
+    }
+
+    Ok(())
+}
+
+fn print_status_table(statuses: &[(String, Result<ServerStatus>)]) {
+    const LOG_WIDTH: usize = 60;
+
+    let headers = [
+        "NAME", "STATE", "HEALTH", "PID", "UPTIME", "CPU", "MEMORY", "PORT", "MODE", "BUILD",
+        "MAP", "PLAYERS", "LAST LOG",
+    ];
+    let rows = statuses
+        .iter()
+        .map(|(name, status)| match status {
+            Ok(status) => {
+                let port = match status.port {
+                    Some(port) if status.port_open => port.to_string(),
+                    Some(port) => format!("{} (closed)", port),
+                    None => "-".to_string(),
+                };
+                let build = match (&status.installed_build, status.needs_update) {
+                    (Some(build), Some(true)) => format!("{} (outdated)", build),
+                    (Some(build), _) => build.clone(),
+                    (None, _) => "-".to_string(),
+                };
+                let last_log = status.last_log_line.as_deref().unwrap_or("-");
+                vec![
+                    name.clone(),
+                    format!("{:?}", status.state),
+                    format!("{:?}", status.health),
+                    optional(status.pid),
+                    optional(status.uptime.map(|uptime| format_duration(uptime.as_secs()))),
+                    optional(status.cpu_percent.map(|cpu| format!("{:.1}%", cpu))),
+                    optional(status.rss_bytes.map(format_bytes)),
+                    port,
+                    optional(status.mode),
+                    build,
+                    optional(status.info.as_ref().map(|info| info.map.clone())),
+                    optional(status.info.as_ref().map(|info| {
+                        format!("{}/{}", info.players.saturating_sub(info.bots), info.max_players)
+                    })),
+                    truncate(last_log, LOG_WIDTH),
+                ]
+            }
+            Err(e) => {
+                let mut row = vec![name.clone(), "Error".to_string()];
+                row.resize(headers.len() - 1, "-".to_string());
+                row.push(truncate(&e.to_string(), LOG_WIDTH));
+                row
+            }
+        })
+        .collect::<Vec<_>>();
+
+    print_table(&headers, &rows);
+}
+
+/// Compares the installed build with the latest one for the instance's
+/// branch. Leaves `needs_update` unknown if either is not available.
+/// `latest_builds` holds the branches already looked up, so each is only
+/// looked up once per command.
+async fn check_needs_update(
+    status: &mut ServerStatus,
+    instance: &InstanceConfig,
+    latest_builds: &mut HashMap<String, Option<String>>,
+) {
+    let Some(installed) = status.installed_build.clone() else {
+        return;
+    };
+    // Pinned instances only move when the pin changes
+    if instance.is_pinned() {
+        status.needs_update = Some(
+            instance
+                .pinned_build
+                .as_ref()
+                .is_some_and(|pinned| *pinned != installed),
+        );
+        return;
+    }
+    let branch = instance.branch_name();
+    let latest = match latest_builds.get(branch) {
+        Some(latest) => latest.clone(),
+        None => {
+            let latest = cached_latest_build(branch).await;
+            latest_builds.insert(branch.to_string(), latest.clone());
+            latest
+        }
+    };
+    if let Some(latest) = latest {
+        status.needs_update = Some(installed != latest);
+    }
+}
+
+/// Latest build ID of a branch, reusing the cached one while it is fresh.
+/// Refreshing needs SteamCMD to be installed already and is tried once with
+/// a short timeout; if that fails, the last known build is used, without
+/// asking Steam again until the cache would have expired.
+async fn cached_latest_build(branch: &str) -> Option<String> {
+    let mut cache = BuildCache::load();
+    let cached = cache.branches.get(branch).cloned();
+    let fresh = |checked_at| Utc::now() - checked_at < BUILD_CACHE_TTL;
+    let checked = cached
+        .as_ref()
+        .is_some_and(|cached| fresh(cached.checked_at));
+    let failed = cache.failed.get(branch).is_some_and(|at| fresh(*at));
+    if checked || failed {
+        return cached.map(|cached| cached.build_id);
+    }
+
+    let Some(steam_manager) = SteamManager::find() else {
+        return cached.map(|cached| cached.build_id);
+    };
+    match steam_manager
+        .peek_latest_build_id(branch, BUILD_CHECK_TIMEOUT)
+        .await
+    {
+        Ok(build_id) => {
+            cache.record(branch, &build_id);
+            if let Err(e) = cache.save() {
+                warn!("{:#}", e);
+            }
+            Some(build_id)
+        }
+        Err(e) => {
+            warn!("Failed to check for CS2 server updates: {:#}", e);
+            cache.record_failure(branch);
+            if let Err(e) = cache.save() {
+                warn!("{:#}", e);
+            }
+            cached.map(|cached| cached.build_id)
+        }
+    }
+}
+
+fn print_status_warnings(statuses: &[(String, Result<ServerStatus>)]) {
+    let warnings: Vec<(&String, &String)> = statuses
+        .iter()
+        .filter_map(|(name, status)| Some((name, status.as_ref().ok()?)))
+        .flat_map(|(name, status)| status.warnings.iter().map(move |warning| (name, warning)))
+        .collect();
+
+    if !warnings.is_empty() {
+        println!();
+    }
+    for (name, warning) in warnings {
+        println!("Warning: {}: {}", name, warning);
+    }
+}
+
+fn print_table(headers: &[&str], rows: &[Vec<String>]) {
+    let mut widths: Vec<usize> = headers.iter().map(|h| h.len()).collect();
+    for row in rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+
+    let format_row = |cells: Vec<&str>| {
+        cells
+            .iter()
+            .zip(&widths)
+            .map(|(cell, width)| format!("{:<width$}", cell, width = width))
+            .collect::<Vec<_>>()
+            .join("  ")
+            .trim_end()
+            .to_string()
+    };
+
+    println!("{}", format_row(headers.to_vec()));
+    for row in rows {
+        println!("{}", format_row(row.iter().map(|c| c.as_str()).collect()));
+    }
+}
+
+fn optional<T: std::fmt::Display>(value: Option<T>) -> String {
+    value
+        .map(|v| v.to_string())
+        .unwrap_or_else(|| "-".to_string())
+}
+
+fn truncate(text: &str, width: usize) -> String {
+    if text.chars().count() <= width {
+        text.to_string()
+    } else {
+        let mut truncated: String = text.chars().take(width - 3).collect();
+        truncated.push_str("...");
+        truncated
+    }
+}
+
+pub async fn query_server(address: &str, show_rules: bool, output: OutputFormat) -> Result<()> {
+    let address = if address.contains(':') {
+        address.to_string()
+    } else {
+        format!("{}:{}", address, crate::runtime::DEFAULT_PORT)
+    };
+    info!("Querying server at {}", address);
+
+    let client = QueryClient::connect(&address, crate::query::DEFAULT_TIMEOUT).await?;
+    let server_info = match client.info().await {
+        Ok(server_info) => server_info,
+        Err(e) => {
+            error!("Failed to query server at {}: {}", address, e);
+            return Err(e);
+        }
+    };
+
+    let players = match client.players().await {
+        Ok(players) => players,
+        Err(e) => {
+            warn!("Failed to query players: {}", e);
+            vec![]
+        }
+    };
+
+    let rules = if show_rules {
+        Some(client.rules().await?)
+    } else {
+        None
+    };
+
+    if output.is_machine() {
+        let rules = rules.map(|rules| rules.into_iter().collect::<std::collections::BTreeMap<_, _>>());
+        return output.emit(
+            "query",
+            &json!({
+                "address": address,
+                "info": server_info,
+                "players": players,
+                "rules": rules,
+            }),
+        );
+    }
+
+    print_server_info(&server_info);
+    print_players(&players);
+    if let Some(rules) = rules {
+        println!("Rules:");
+        for (name, value) in rules {
+            println!("  {} = {}", name, value);
+        }
+    }
+
+    Ok(())
+}
+
+fn print_server_info(server_info: &ServerInfo) {
+    let server_type = match server_info.server_type {
+        'd' => "dedicated",
+        'l' => "listen",
+        'p' => "SourceTV",
+        _ => "unknown",
+    };
+    let environment = match server_info.environment {
+        'l' => "Linux",
+        'w' => "Windows",
+        'm' | 'o' => "macOS",
+        _ => "unknown",
+    };
+
+    println!("  Name:    {}", server_info.name);
+    println!(
+        "  Game:    {} ({}, app {})",
+        server_info.game, server_info.folder, server_info.app_id
+    );
+    println!("  Type:    {} on {}", server_type, environment);
+    println!("  Map:     {}", server_info.map);
+    println!(
+        "  Players: {}/{} ({} bots)",
+        server_info.players, server_info.max_players, server_info.bots
+    );
+    println!(
+        "  VAC:     {}",
+        if server_info.vac { "secured" } else { "insecure" }
+    );
+    println!(
+        "  Access:  {}",
+        if server_info.password {
+            "password protected"
+        } else {
+            "public"
+        }
+    );
+    println!(
+        "  Version: {} (protocol {})",
+        server_info.version, server_info.protocol
+    );
+    if let Some(port) = server_info.port {
+        println!("  Port:    {}", port);
+    }
+    if let Some(keywords) = server_info.keywords.as_deref().filter(|k| !k.is_empty()) {
+        println!("  Tags:    {}", keywords);
+    }
+}
+
+fn print_players(players: &[PlayerInfo]) {
+    if players.is_empty() {
+        return;
+    }
+
+    println!("  {:<32} {:>6} {:>10}", "Player", "Score", "Time");
+    for player in players {
+        println!(
+            "  {:<32} {:>6} {:>10}",
+            player.name,
+            player.score,
+            format_duration(player.duration as u64)
+        );
+    }
+}
+
+pub async fn check_update(name: &str, output: OutputFormat) -> Result<()> {
+    let config = Config::load_or_default()?;
+    let instance = config.get_instance(name)?;
+    let branch = instance.branch_name();
+
+    let installed = AppManifest::load(&instance.path)?;
+    let steam_manager = SteamManager::new()?.with_output(output);
+    let latest = steam_manager.latest_build_id(branch).await?;
+
+    let mut cache = BuildCache::load();
+    cache.record(branch, &latest);
+    if let Err(e) = cache.save() {
+        warn!("{:#}", e);
+    }
+
+    let installed_build = installed.map(|manifest| manifest.build_id);
+    let target = match &instance.pinned_build {
+        Some(pinned) if instance.is_pinned() => pinned,
+        _ => &latest,
+    };
+    let needs_update = installed_build.as_deref() != Some(target.as_str());
+    info!(
+        "Server '{}': installed build {:?}, latest build {} on branch {}",
+        name, installed_build, latest, branch
+    );
+
+    if output.is_machine() {
+        return output.emit(
+            "update_check",
+            &json!({
+                "name": name,
+                "branch": branch,
+                "installed_build": installed_build,
+                "latest_build": latest,
+                "pinned_build": instance.pinned_build,
+                "pinned": instance.is_pinned(),
+                "needs_update": needs_update,
+            }),
+        );
+    }
+    if instance.is_pinned() {
+        println!(
+            "Server '{}' is pinned to build {} (installed: {}, latest: {})",
+            name,
+            instance.pinned_build.as_deref().unwrap_or("-"),
+            installed_build.as_deref().unwrap_or("-"),
+            latest
+        );
+        return Ok(());
+    }
+    match installed_build {
+        None => println!(
+            "Server '{}' has no installed build, latest is {} ({})",
+            name, latest, branch
+        ),
+        Some(installed) if needs_update => println!(
+            "Update available for '{}': build {} -> {} ({})",
+            name, installed, latest, branch
+        ),
+        Some(installed) => println!(
+            "Server '{}' is up to date (build {}, {})",
+            name, installed, branch
+        ),
+    }
+    Ok(())
+}
+
+pub async fn update_server(
+    name: &str,
+    options: &DownloadOptions,
+    validate: bool,
+    output: OutputFormat,
+) -> Result<()> {
+    info!("Updating server: {}", name);
+
+    let mut config = Config::load_or_default()?;
+    // A new branch is only recorded once it is installed
+    let mut updated = config.get_instance(name)?.clone();
+    let changed = options.apply(&mut updated);
+
+    let login = SteamManager::steam_login(options.steam_user.as_deref())?;
+    let steam_manager = SteamManager::new()?.with_output(output);
+    let result = install_instance_build(&steam_manager, &updated, login, validate)
+        .await
+        .and_then(|_| {
+            if changed {
+                *config.get_instance_mut(name)? = updated;
+                config.save()?;
+            }
+            Ok(())
+        });
+    let instance = config.get_instance(name)?;
+    let server_path = &instance.path;
+    let refreshed = result.and_then(|_| sync_overlays(&config, instance));
+    let refreshed = match refreshed {
+        Ok(refreshed) => refreshed,
+        Err(e) => {
+            error!("Failed to update server '{}': {}", name, e);
+            return Err(e);
+        }
+    };
+
+    let build = AppManifest::load(server_path)?.map(|manifest| manifest.build_id);
+    info!("Server '{}' updated successfully", name);
+    if output.is_machine() {
+        output.emit(
+            "update",
+            &json!({
+                "name": name,
+                "path": server_path,
+                "branch": instance.branch_name(),
+                "build": build,
+                "pinned": instance.is_pinned(),
+                "base": instance.base,
+                "refreshed": refreshed,
+            }),
+        )?;
+    } else {
+        match build {
+            Some(build) => println!("Server '{}' updated successfully (build {})", name, build),
+            None => println!("Server '{}' updated successfully", name),
+        }
+        if refreshed.len() > 1 {
+            println!("Refreshed servers sharing its base: {}", refreshed.join(", "));
+        }
+    }
+    Ok(())
+}
+
+/// Updates an instance to the latest build of its branch, or to its pinned
+/// build. For an overlay instance the shared base is updated instead.
+async fn install_instance_build(
+    steam_manager: &SteamManager,
+    instance: &InstanceConfig,
+    login: SteamLogin,
+    validate: bool,
+) -> Result<()> {
+    if instance.is_pinned() {
+        return install_pinned_build(steam_manager, instance, login, validate).await;
+    }
+    let branch = SteamBranch::new(
+        instance.branch.as_deref(),
+        instance.branch_password.as_deref(),
+    );
+    let install_path = instance.base.as_ref().unwrap_or(&instance.path);
+    std::fs::create_dir_all(install_path)
+        .with_context(|| format!("Failed to create directory: {:?}", install_path))?;
+    steam_manager
+        .update_cs2_server(install_path, login, &branch, validate)
+        .await
+}
+
+/// Links every overlay on the base of `instance` to the base files again,
+/// after the base was updated. Pinned overlays keep their own files. Returns
+/// the names of the refreshed servers.
+fn sync_overlays(config: &Config, instance: &InstanceConfig) -> Result<Vec<String>> {
+    let Some(base) = instance.base.as_deref().filter(|_| !instance.is_pinned()) else {
+        return Ok(vec![]);
+    };
+    let mut refreshed = vec![];
+    for (name, other) in &config.servers {
+        if other.base.as_deref() != Some(base) || other.is_pinned() {
+            continue;
+        }
+        let changed = overlay::sync(base, &other.path)
+            .with_context(|| format!("Failed to refresh server '{}' from {:?}", name, base))?;
+        info!("Refreshed {} files of server '{}'", changed, name);
+        refreshed.push(name.clone());
+    }
+    Ok(refreshed)
+}
+
+/// Brings a pinned instance to its pinned depot manifests, downloading only
+/// the depots that differ from what is installed. With `validate`, every
+/// pinned depot is downloaded again and the files that differ are restored.
+async fn install_pinned_build(
+    steam_manager: &SteamManager,
+    instance: &InstanceConfig,
+    login: SteamLogin,
+    validate: bool,
+) -> Result<()> {
+    let installed = AppManifest::load(&instance.path)?
+        .context("Server files are not installed; install them before pinning a build")?;
+    let missing: BTreeMap<String, String> = instance
+        .pinned_manifests
+        .iter()
+        .filter(|(depot, manifest)| validate || installed.depots.get(*depot) != Some(*manifest))
+        .map(|(depot, manifest)| (depot.clone(), manifest.clone()))
+        .collect();
+    if missing.is_empty() {
+        info!("Pinned depot manifests are already installed");
+        return Ok(());
+    }
+
+    steam_manager
+        .install_depots(&instance.path, login, &missing)
+        .await?;
+    AppManifest::record_depots(&instance.path, instance.pinned_build.as_deref(), &missing)
+}
+
+pub async fn verify_server(
+    name: &str,
+    steam_user: Option<&str>,
+    output: OutputFormat,
+) -> Result<()> {
+    info!("Verifying server files: {}", name);
+
+    let config = Config::load_or_default()?;
+    let instance = config.get_instance(name)?;
+    let server_path = &instance.path;
+    let build_before = AppManifest::load(server_path)?
+        .context("Server files are not installed")?
+        .build_id;
+    let before = FileSnapshot::capture(server_path)?;
+
+    let login = SteamManager::steam_login(steam_user)?;
+    let steam_manager = SteamManager::new()?.with_output(output);
+    let result = install_instance_build(&steam_manager, instance, login, true).await;
+    if let Err(e) = result.and_then(|_| sync_overlays(&config, instance)) {
+        error!("Failed to verify server '{}': {}", name, e);
+        return Err(e);
+    }
+
+    let repaired = FileSnapshot::capture(server_path)?.changed_since(&before);
+    let build = AppManifest::load(server_path)?.map(|manifest| manifest.build_id);
+    let updated = build.as_deref() != Some(build_before.as_str());
+    info!(
+        "Server '{}' verified, {} files repaired",
+        name,
+        repaired.len()
+    );
+
+    if output.is_machine() {
+        return output.emit(
+            "verify",
+            &json!({
+                "name": name,
+                "path": server_path,
+                "build": build,
+                "previous_build": build_before,
+                "updated": updated,
+                "repaired": repaired,
+            }),
+        );
+    }
+    if repaired.is_empty() {
+        println!("All server files of '{}' are intact", name);
+    } else {
+        println!("Repaired {} file(s) of '{}':", repaired.len(), name);
+        for path in &repaired {
+            println!("  {}", path.display());
+        }
+    }
+    if updated {
+        println!(
+            "Steam had a newer build, so the files were also updated from build {} to {}",
+            build_before,
+            build.as_deref().unwrap_or("-")
+        );
+    }
+    Ok(())
+}
+
+pub async fn configure_server(
+    name: &str,
+    key: &str,
+    value: &str,
+    force: bool,
+    layer: ConfigLayer,
+    output: OutputFormat,
+) -> Result<()> {
+    // Secrets are confirmed without echoing them
+    let shown = match cvars::schema().is_protected(key) && !value.is_empty() {
+        true => PROTECTED_MASK,
+        false => value,
+    };
+    info!(
+        "Configuring server '{}' setting '{}' to '{}'",
+        name, key, shown
+    );
+
+    let config = Config::load_or_default()?;
+    let server_path = config.get_server_path(name)?;
+    let cfg_path = server_path.join("server.cfg");
+
+    let mut server_config = if cfg_path.exists() {
+        ServerConfig::load(&cfg_path)?
+    } else {
+        warn!("No server.cfg found, creating a default one");
+        ServerConfig::default()
+    };
+
+    if let Err(e) = cvars::schema().check(key, value) {
+        if !force {
+            anyhow::bail!("{:#} (use --force to write it anyway)", e);
+        }
+        warn!("Writing {} despite validation failure: {:#}", key, e);
+    }
+    let warning = cvars::schema().typo_warning(key);
+
+    if let Err(e) = server_config.set(key, value) {
+        error!("Invalid configuration key '{}': {}", key, e);
+        return Err(e);
+    }
+
+    // A layer setting the cvar too would win at the next `config render`
+    let mut overridden_by = None;
+    match layer {
+        // The instance layer wins over every other, so server.cfg gets the
+        // value `config render` would write
+        ConfigLayer::Instance => {
+            let mut overrides = InstanceOverrides::load(server_path)?;
+            overrides.set_cvar(key, value);
+            overrides.save(server_path)?;
+        }
+        ConfigLayer::Cfg => {
+            overridden_by = profile::resolve(server_path)?
+                .remove(&key.to_ascii_lowercase())
+                .filter(|entry| entry.value != value)
+                .map(|entry| entry.source);
+        }
+    }
+
+    if let Err(e) = server_config.save(&cfg_path) {
+        error!("Failed to save server configuration: {}", e);
+        return Err(e);
+    }
+
+    info!("Configuration updated successfully");
+    if output.is_machine() {
+        output.emit(
+            "config",
+            &json!({
+                "name": name,
+                "key": key,
+                "value": shown,
+                "layer": layer,
+                "overridden_by": overridden_by,
+                "warning": warning,
+            }),
+        )?;
+    } else {
+        if let Some(warning) = &warning {
+            println!("Warning: {}", warning);
+        }
+        println!("Configuration updated: {} = {}", key, shown);
+        if let Some(source) = overridden_by {
+            println!(
+                "Note: {} is also set by {}, `config render` will replace this value; use --layer instance to keep it",
+                key, source
+            );
+        }
+    }
+    Ok(())
+}
+
+pub async fn unset_config(
+    name: &str,
+    key: &str,
+    layer: ConfigLayer,
+    output: OutputFormat,
+) -> Result<()> {
+    info!("Removing setting '{}' from server '{}'", key, name);
+
+    let config = Config::load_or_default()?;
+    let server_path = config.get_server_path(name)?;
+    let cfg_path = server_path.join("server.cfg");
+
+    let mut server_config = ServerConfig::load(&cfg_path)?;
+    let removed = match layer {
+        ConfigLayer::Cfg => server_config.unset(key),
+        ConfigLayer::Instance => {
+            let mut overrides = InstanceOverrides::load(server_path)?;
+            let removed = overrides.unset_cvar(key);
+            if removed {
+                overrides.save(server_path)?;
+                // A lower layer may set the cvar too, its value applies now
+                match profile::resolve(server_path)?.get(&key.to_ascii_lowercase()) {
+                    Some(entry) => server_config.set(key, &entry.value)?,
+                    None => {
+                        server_config.unset(key);
+                    }
+                }
+            }
+            removed
+        }
+    };
+    if removed {
+        server_config.save(&cfg_path)?;
+    }
+
+    if output.is_machine() {
+        output.emit(
+            "config_unset",
+            &json!({ "name": name, "key": key, "removed": removed }),
+        )?;
+    } else if removed {
+        println!("Configuration updated: {} removed", key);
+    } else {
+        println!("'{}' is not set in {:?}", key, cfg_path);
+    }
+    Ok(())
+}
+
+pub async fn show_config(name: &str, output: OutputFormat) -> Result<()> {
+    let config = Config::load_or_default()?;
+    let server_path = config.get_server_path(name)?;
+    let server_config = ServerConfig::load(&server_path.join("server.cfg"))?;
+    let schema = cvars::schema();
+
+    // Secrets such as passwords are masked, read server.cfg to see them
+    let settings: Vec<(String, String, Option<&str>)> = server_config
+        .cvars()
+        .into_iter()
+        .map(|(key, value)| {
+            let spec = schema.get(&key);
+            let value = match spec.is_some_and(|spec| spec.protected) && !value.is_empty() {
+                true => PROTECTED_MASK.to_string(),
+                false => value,
+            };
+            (key, value, spec.map(|spec| spec.description.as_str()))
+        })
+        .collect();
+
+    if output.is_machine() {
+        let settings: Vec<_> = settings
+            .iter()
+            .map(|(key, value, description)| {
+                json!({
+                    "key": key,
+                    "value": value,
+                    "protected": schema.is_protected(key),
+                    "description": description,
+                })
+            })
+            .collect();
+        output.emit(
+            "config_show",
+            &json!({ "name": name, "mode": server_config.game_mode(), "settings": settings }),
+        )?;
+        return Ok(());
+    }
+
+    match server_config.game_mode() {
+        Some(mode) => println!("Game mode: {}\n", mode),
+        None => println!("Game mode: not a known preset\n"),
+    }
+    let rows: Vec<Vec<String>> = settings
+        .into_iter()
+        .map(|(key, value, description)| {
+            vec![key, value, description.unwrap_or("-").to_string()]
+        })
+        .collect();
+    print_table(&["SETTING", "VALUE", "DESCRIPTION"], &rows);
+    Ok(())
+}
+
+pub async fn show_resolved_config(name: &str, output: OutputFormat) -> Result<()> {
+    let config = Config::load_or_default()?;
+    let server_path = config.get_server_path(name)?;
+    let cfg_path = server_path.join("server.cfg");
+    let server_config = match cfg_path.exists() {
+        true => ServerConfig::load(&cfg_path)?,
+        false => ServerConfig::default(),
+    };
+    let schema = cvars::schema();
+    let mask = |key: &str, value: &str| match schema.is_protected(key) && !value.is_empty() {
+        true => PROTECTED_MASK.to_string(),
+        false => value.to_string(),
+    };
+
+    let resolved = profile::resolve(server_path)?;
+    let mut stale = vec![];
+    let mut rows = vec![];
+    let mut settings = vec![];
+    for (key, entry) in &resolved {
+        let rendered = server_config.get(key) == Some(entry.value.as_str());
+        if !rendered {
+            stale.push(key.clone());
+        }
+        let overrides: Vec<String> = entry.overrides.iter().map(|s| s.to_string()).collect();
+        rows.push(vec![
+            key.clone(),
+            mask(key, &entry.value),
+            entry.source.to_string(),
+            if overrides.is_empty() { "-".to_string() } else { overrides.join(", ") },
+        ]);
+        settings.push(json!({
+            "key": key,
+            "value": mask(key, &entry.value),
+            "source": entry.source,
+            "overrides": entry.overrides,
+            "rendered": rendered,
+        }));
+    }
+    // Settings only present in server.cfg, e.g. set with `config set`
+    for (key, value) in server_config.cvars() {
+        if resolved.contains_key(&key.to_ascii_lowercase()) {
+            continue;
+        }
+        rows.push(vec![key.clone(), mask(&key, &value), "server.cfg".to_string(), "-".to_string()]);
+        settings.push(json!({
+            "key": key,
+            "value": mask(&key, &value),
+            "source": "server.cfg",
+            "rendered": true,
+        }));
+    }
+
+    if output.is_machine() {
+        output.emit("config_resolved", &json!({ "name": name, "settings": settings }))?;
+        return Ok(());
+    }
+
+    print_table(&["SETTING", "VALUE", "SOURCE", "OVERRIDES"], &rows);
+    if !stale.is_empty() {
+        println!(
+            "\nserver.cfg differs for {}; run `cs2-server-cli config render {}` to apply",
+            stale.join(", "),
+            name
+        );
+    }
+    Ok(())
+}
+
+pub async fn render_config(name: &str, force: bool, output: OutputFormat) -> Result<()> {
+    info!("Rendering configuration layers for server '{}'", name);
+
+    let config = Config::load_or_default()?;
+    let server_path = config.get_server_path(name)?;
+    let cfg_path = server_path.join("server.cfg");
+
+    let mut server_config = if cfg_path.exists() {
+        ServerConfig::load(&cfg_path)?
+    } else {
+        warn!("No server.cfg found, creating a default one");
+        ServerConfig::default()
+    };
+
+    let resolved = profile::resolve(server_path)?;
+    let mut changed = vec![];
+    let mut warnings = vec![];
+    for (key, entry) in &resolved {
+        if let Err(e) = cvars::schema().check(key, &entry.value) {
+            if !force {
+                anyhow::bail!("{:#} (from {}, use --force to render anyway)", e, entry.source);
+            }
+            warn!("Rendering {} despite validation failure: {:#}", key, e);
+        }
+        if let Some(warning) = cvars::schema().typo_warning(key) {
+            warnings.push(format!("{} (from {})", warning, entry.source));
+        }
+        if server_config.get(key) != Some(entry.value.as_str()) {
+            server_config.set(key, &entry.value)?;
+            changed.push(key.clone());
+        }
+    }
+
+    if !changed.is_empty() {
+        server_config.save(&cfg_path)?;
     }
 
-    info!(
-        "CS2 server '{}' installed successfully at {:?}",
-        name, server_dir
-    );
-    println!(
-        "CS2 server '{}' installed successfully at {:?}",
-        name, server_dir
-    );
+    info!("Rendered {} setting(s) into {:?}", changed.len(), cfg_path);
+    if output.is_machine() {
+        return output.emit(
+            "config_render",
+            &json!({ "name": name, "changed": changed, "warnings": warnings }),
+        );
+    }
+    for warning in &warnings {
+        println!("Warning: {}", warning);
+    }
+    if changed.is_empty() {
+        println!("{:?} is up to date", cfg_path);
+    } else {
+        println!("Updated {} in {:?}", changed.join(", "), cfg_path);
+    }
     Ok(())
 }
 
-pub async fn start_server(name: &str) -> Result<()> {
-    info!("Starting server: {}", name);
-
+pub async fn set_profiles(
+    name: &str,
+    profiles: &[String],
+    clear: bool,
+    output: OutputFormat,
+) -> Result<()> {
     let config = Config::load_or_default()?;
     let server_path = config.get_server_path(name)?;
-
-    let mut server_manager = ServerManager::new(server_path.clone());
-    if let Err(e) = server_manager.start().await {
-        error!("Failed to start server '{}': {}", name, e);
-        return Err(e);
+    let mut overrides = InstanceOverrides::load(server_path)?;
+
+    if clear {
+        overrides.profiles.clear();
+        overrides.save(server_path)?;
+        info!("Server '{}' no longer uses profiles", name);
+    } else if !profiles.is_empty() {
+        for profile in profiles {
+            let path = profile::profile_path(profile);
+            if !path.exists() {
+                anyhow::bail!("Profile '{}' not found at {:?}", profile, path);
+            }
+        }
+        overrides.profiles = profiles.to_vec();
+        overrides.save(server_path)?;
+        info!("Server '{}' now uses profiles {:?}", name, profiles);
     }
 
-    info!("Server '{}' started successfully", name);
-    println!("Server '{}' started successfully", name);
+    if output.is_machine() {
+        output.emit("config_profiles", &json!({ "name": name, "profiles": overrides.profiles }))?;
+    } else if overrides.profiles.is_empty() {
+        println!("Server '{}' uses no profiles", name);
+    } else {
+        println!("Server '{}' uses profiles: {}", name, overrides.profiles.join(", "));
+    }
     Ok(())
 }
 
-pub async fn stop_server(name: &str) -> Result<()> {
-    info!("Stopping server: {}", name);
+pub async fn set_game_mode(name: &str, mode: GameMode, output: OutputFormat) -> Result<()> {
+    info!("Switching server '{}' to {} mode", name, mode);
 
     let config = Config::load_or_default()?;
     let server_path = config.get_server_path(name)?;
+    let cfg_path = server_path.join("server.cfg");
+
+    let mut server_config = ServerConfig::load(&cfg_path)?;
+    server_config.apply_mode(mode)?;
+    server_config.save(&cfg_path)?;
+
+    let preset = mode.preset();
+    if output.is_machine() {
+        output.emit(
+            "config_mode",
+            &json!({
+                "name": name,
+                "mode": mode,
+                "game_type": preset.game_type,
+                "game_mode": preset.game_mode,
+                "maxplayers": preset.maxplayers,
+                "mapgroup": preset.mapgroup,
+            }),
+        )?;
+    } else {
+        println!(
+            "Server '{}' set to {} (game_type {}, game_mode {}, maxplayers {}, mapgroup {})",
+            name, mode, preset.game_type, preset.game_mode, preset.maxplayers, preset.mapgroup
+        );
+    }
+    Ok(())
+}
 
-    let mut server_manager = ServerManager::new(server_path.clone());
-    if let Err(e) = server_manager.stop().await {
-        error!("Failed to stop server '{}': {}", name, e);
-        return Err(e);
+pub async fn validate_config(name: &str, output: OutputFormat) -> Result<()> {
+    let config = Config::load_or_default()?;
+    let server_path = config.get_server_path(name)?;
+    let cfg_path = server_path.join("server.cfg");
+    let issues = ServerConfig::load(&cfg_path)?.validate();
+    let errors = issues
+        .iter()
+        .filter(|issue| issue.severity == Severity::Error)
+        .count();
+
+    if output.is_machine() {
+        output.emit(
+            "config_validate",
+            &json!({ "name": name, "valid": errors == 0, "issues": issues }),
+        )?;
+    } else if issues.is_empty() {
+        println!("{:?} is valid", cfg_path);
+    } else {
+        for issue in &issues {
+            let severity = match issue.severity {
+                Severity::Error => "error",
+                Severity::Warning => "warning",
+            };
+            let line = issue.line.map(|line| format!("line {}: ", line));
+            println!("{}: {}{}", severity, line.unwrap_or_default(), issue.message);
+        }
     }
 
-    info!("Server '{}' stopped successfully", name);
-    println!("Server '{}' stopped successfully", name);
+    if errors > 0 {
+        anyhow::bail!("{:?} has {} error(s)", cfg_path, errors);
+    }
     Ok(())
 }
 
-pub async fn server_status(name: Option<&str>) -> Result<()> {
-    info!("Checking server status");
-
+pub async fn show_instance(name: &str, output: OutputFormat) -> Result<()> {
     let config = Config::load_or_default()?;
+    let instance = config.get_instance(name)?;
+
+    if output.is_machine() {
+        // The token is a secret, report only whether one is set
+        let mut data = serde_json::to_value(instance)?;
+        if let Some(fields) = data.as_object_mut() {
+            fields.remove("gslt");
+            fields.insert("gslt_set".to_string(), json!(instance.gslt.is_some()));
+            fields.remove("branch_password");
+            fields.insert(
+                "branch_password_set".to_string(),
+                json!(instance.branch_password.is_some()),
+            );
+            fields.insert("name".to_string(), json!(name));
+        }
+        return output.emit("instance", &data);
+    }
 
-    if let Some(name) = name {
-        let server_path = config.get_server_path(name)?;
-        let mut server_manager = ServerManager::new(server_path.clone());
-        match server_manager.get_status().await {
-            Ok(status) => {
-                info!("Server '{}' status: {:?}", name, status);
-                println!("Server '{}': {:?}", name, status);
-            }
-            Err(e) => {
-                error!("Failed to get status for server '{}': {}", name, e);
-                return Err(e);
-            }
+    let pinned_manifests: Vec<String> = instance
+        .pinned_manifests
+        .iter()
+        .map(|(depot, manifest)| format!("{}={}", depot, manifest))
+        .collect();
+    let list = |values: &[String], separator: &str| match values.is_empty() {
+        true => "-".to_string(),
+        false => values.join(separator),
+    };
+    let rows = vec![
+        vec!["path".to_string(), instance.path.display().to_string()],
+        vec!["port".to_string(), optional(instance.port)],
+        vec!["bind_ip".to_string(), optional(instance.bind_ip.as_deref())],
+        vec!["launch_options".to_string(), list(&instance.launch_options, " ")],
+        vec![
+            "gslt".to_string(),
+            optional(instance.gslt.as_ref().map(|_| PROTECTED_MASK)),
+        ],
+        vec!["tags".to_string(), list(&instance.tags, ",")],
+        vec!["branch".to_string(), optional(instance.branch.as_deref())],
+        vec![
+            "branch_password".to_string(),
+            optional(instance.branch_password.as_ref().map(|_| PROTECTED_MASK)),
+        ],
+        vec!["pinned_build".to_string(), optional(instance.pinned_build.as_deref())],
+        vec!["pinned_manifests".to_string(), list(&pinned_manifests, ",")],
+        vec![
+            "base".to_string(),
+            optional(instance.base.as_ref().map(|base| base.display())),
+        ],
+        vec!["owner".to_string(), optional(instance.owner.as_deref())],
+        vec!["autostart".to_string(), instance.autostart.to_string()],
+        vec!["created_at".to_string(), optional(instance.created_at)],
+        vec!["updated_at".to_string(), instance.updated_at.to_string()],
+    ];
+    print_table(&["FIELD", "VALUE"], &rows);
+    Ok(())
+}
+
+pub async fn set_instance_field(
+    name: &str,
+    field: &str,
+    value: &str,
+    output: OutputFormat,
+) -> Result<()> {
+    // Secrets are confirmed without echoing them
+    let shown = match field {
+        "branch_password" if !value.is_empty() => PROTECTED_MASK,
+        _ => value,
+    };
+    info!("Setting instance field '{}' of '{}' to '{}'", field, name, shown);
+
+    let mut config = Config::load_or_default()?;
+    let instance = config.get_instance_mut(name)?;
+    instance.set(field, value)?;
+    if field == "branch" {
+        rebase_overlay(instance);
+    }
+    if let Err(e) = config.save() {
+        error!("Failed to save tool configuration: {}", e);
+        return Err(e);
+    }
+
+    if output.is_machine() {
+        output.emit(
+            "instance_set",
+            &json!({ "name": name, "field": field, "value": shown }),
+        )?;
+    } else if value.is_empty() {
+        println!("Instance '{}': {} cleared", name, field);
+    } else {
+        println!("Instance '{}': {} = {}", name, field, shown);
+    }
+    Ok(())
+}
+
+pub async fn pin_instance(
+    name: &str,
+    manifests: &[String],
+    build: Option<&str>,
+    output: OutputFormat,
+) -> Result<()> {
+    let mut config = Config::load_or_default()?;
+    let instance = config.get_instance_mut(name)?;
+
+    let (pinned_manifests, pinned_build) = if manifests.is_empty() {
+        // Hold the instance at whatever is installed right now
+        let installed = AppManifest::load(&instance.path)?.with_context(|| {
+            format!(
+                "Server '{}' has no installed build to pin, pass --manifest instead",
+                name
+            )
+        })?;
+        if installed.depots.is_empty() {
+            anyhow::bail!("The app manifest of '{}' lists no installed depots", name);
         }
+        let build = build.map(str::to_string).unwrap_or(installed.build_id);
+        (installed.depots, Some(build))
     } else {
-        for server_name in config.list_servers() {
-            let server_path = config.get_server_path(&server_name)?;
-            let mut server_manager = ServerManager::new(server_path.clone());
-            match server_manager.get_status().await {
-                Ok(status) => {
-                    info!("Server '{}' status: {:?}", server_name, status);
-                    println!("{}: {:?}", server_name, status);
-                }
-                Err(e) => {
-                    warn!("Failed to get status for server '{}': {}", server_name, e);
-                    println!("{}: Error - {}", server_name, e);
-                }
-            }
+        let mut pinned = BTreeMap::new();
+        for manifest in manifests {
+            let (depot, manifest_id) = manifest
+                .split_once('=')
+                .filter(|(depot, manifest_id)| {
+                    [depot, manifest_id]
+                        .iter()
+                        .all(|id| !id.is_empty() && id.chars().all(|c| c.is_ascii_digit()))
+                })
+                .with_context(|| {
+                    format!("Invalid manifest '{}', expected DEPOT=MANIFEST", manifest)
+                })?;
+            pinned.insert(depot.to_string(), manifest_id.to_string());
         }
+        (pinned, build.map(str::to_string))
+    };
+
+    instance.pinned_manifests = pinned_manifests;
+    instance.pinned_build = pinned_build;
+    instance.touch();
+    let instance = instance.clone();
+    if let Err(e) = config.save() {
+        error!("Failed to save tool configuration: {}", e);
+        return Err(e);
     }
 
+    info!("Pinned '{}' to {:?}", name, instance.pinned_manifests);
+    if output.is_machine() {
+        output.emit(
+            "instance_pin",
+            &json!({
+                "name": name,
+                "pinned_build": instance.pinned_build,
+                "pinned_manifests": instance.pinned_manifests,
+            }),
+        )?;
+    } else {
+        println!(
+            "Instance '{}' pinned to build {}; run `update {}` to install it",
+            name,
+            instance.pinned_build.as_deref().unwrap_or("(unknown)"),
+            name
+        );
+        for (depot, manifest) in &instance.pinned_manifests {
+            println!("  depot {} manifest {}", depot, manifest);
+        }
+    }
     Ok(())
 }
 
-pub async fn update_server(name: &str) -> Result<()> {
-    info!("Updating server: {}", name);
-
-    let config = Config::load_or_default()?;
-    let server_path = config.get_server_path(name)?;
-
-    let steam_manager = SteamManager::new()?;
-    if let Err(e) = steam_manager.update_cs2_server(&server_path).await {
-        error!("Failed to update server '{}': {}", name, e);
+pub async fn unpin_instance(name: &str, output: OutputFormat) -> Result<()> {
+    let mut config = Config::load_or_default()?;
+    let instance = config.get_instance_mut(name)?;
+    let was_pinned = instance.is_pinned();
+    instance.pinned_manifests.clear();
+    instance.pinned_build = None;
+    instance.touch();
+    let overlay = instance.base.clone().map(|base| (base, instance.path.clone()));
+    if let Err(e) = config.save() {
+        error!("Failed to save tool configuration: {}", e);
         return Err(e);
     }
 
-    info!("Server '{}' updated successfully", name);
-    println!("Server '{}' updated successfully", name);
+    // The depot files of the pinned build were not replaced by the user, so
+    // the overlay goes back to the base files
+    if let Some((base, path)) = overlay.filter(|_| was_pinned) {
+        let changed = overlay::restore(&base, &path)
+            .with_context(|| format!("Failed to link server '{}' to {:?} again", name, base))?;
+        info!(
+            "Linked {} files of server '{}' to the base again",
+            changed, name
+        );
+    }
+
+    if output.is_machine() {
+        output.emit(
+            "instance_unpin",
+            &json!({ "name": name, "was_pinned": was_pinned }),
+        )?;
+    } else if was_pinned {
+        println!(
+            "Instance '{}' unpinned; the next update follows its branch again",
+            name
+        );
+    } else {
+        println!("Instance '{}' was not pinned", name);
+    }
     Ok(())
 }
 
-pub async fn configure_server(name: &str, key: &str, value: &str) -> Result<()> {
-    info!(
-        "Configuring server '{}' setting '{}' to '{}'",
-        name, key, value
-    );
+pub async fn set_token(name: &str, token: Option<&str>, output: OutputFormat) -> Result<()> {
+    let token = match token {
+        Some(token) => token.trim().to_string(),
+        None => rpassword::prompt_password("Login token: ")
+            .context("Failed to read login token")?
+            .trim()
+            .to_string(),
+    };
+    if token.len() != 32 || !token.chars().all(|c| c.is_ascii_hexdigit()) {
+        anyhow::bail!("That does not look like a login token (expected 32 hexadecimal characters)");
+    }
 
-    let config = Config::load_or_default()?;
-    let server_path = config.get_server_path(name)?;
+    let mut config = Config::load_or_default()?;
+    let instance = config.get_instance_mut(name)?;
+    instance.gslt = Some(token);
+    instance.touch();
+    let server_path = instance.path.clone();
+    config.save()?;
+    info!("Stored login token for server '{}'", name);
+
+    // The token lives in the instance metadata only
+    let cfg_path = server_path.join("server.cfg");
+    let mut removed_from_cfg = false;
+    if cfg_path.exists() {
+        let mut server_config = ServerConfig::load(&cfg_path)?;
+        if server_config.unset(TOKEN_CVAR) {
+            server_config.save(&cfg_path)?;
+            removed_from_cfg = true;
+            info!("Removed {} from {:?}", TOKEN_CVAR, cfg_path);
+        }
+    }
 
-    let mut server_config = match ServerConfig::load(&server_path.join("server.cfg")) {
-        Ok(cfg) => cfg,
-        Err(e) => {
-            warn!("Could not load existing config, creating new one: {}", e);
-            ServerConfig::default()
+    if output.is_machine() {
+        output.emit(
+            "token_set",
+            &json!({ "name": name, "removed_from_server_cfg": removed_from_cfg }),
+        )?;
+    } else {
+        println!("Login token stored for '{}', it is used from the next start", name);
+        if removed_from_cfg {
+            println!("Removed {} from {:?}", TOKEN_CVAR, cfg_path);
         }
-    };
+    }
+    Ok(())
+}
 
-    if let Err(e) = server_config.set(key, value) {
-        error!("Invalid configuration key '{}': {}", key, e);
-        return Err(e);
+pub async fn show_token(name: &str, reveal: bool, output: OutputFormat) -> Result<()> {
+    let config = Config::load_or_default()?;
+    let instance = config.get_instance(name)?;
+
+    let shown = instance.gslt.as_deref().map(|token| match reveal {
+        true => token.to_string(),
+        false => mask_token(token),
+    });
+
+    if output.is_machine() {
+        output.emit(
+            "token",
+            &json!({ "name": name, "set": instance.gslt.is_some(), "token": shown }),
+        )?;
+    } else {
+        match shown {
+            Some(token) => println!("{}", token),
+            None => println!("No login token set for '{}'", name),
+        }
     }
+    Ok(())
+}
 
-    if let Err(e) = server_config.save(&server_path.join("server.cfg")) {
-        error!("Failed to save server configuration: {}", e);
-        return Err(e);
+pub async fn clear_token(name: &str, output: OutputFormat) -> Result<()> {
+    let mut config = Config::load_or_default()?;
+    let instance = config.get_instance_mut(name)?;
+    let was_set = instance.gslt.take().is_some();
+    if was_set {
+        instance.touch();
+        config.save()?;
+        info!("Removed login token of server '{}'", name);
     }
 
-    info!("Configuration updated successfully");
-    println!("Configuration updated: {} = {}", key, value);
+    if output.is_machine() {
+        output.emit("token_clear", &json!({ "name": name, "removed": was_set }))?;
+    } else if was_set {
+        println!("Login token of '{}' removed", name);
+    } else {
+        println!("No login token set for '{}'", name);
+    }
     Ok(())
 }
 
-pub async fn install_map(name: &str, map: &str) -> Result<()> {
+/// Keeps only the last four characters of a token.
+fn mask_token(token: &str) -> String {
+    let hidden = token.chars().count().saturating_sub(4);
+    let visible: String = token.chars().skip(hidden).collect();
+    format!("{}{}", "*".repeat(hidden), visible)
+}
+
+pub async fn install_map(name: &str, map: &str, output: OutputFormat) -> Result<()> {
     info!("Installing map '{}' for server '{}'", map, name);
 
     let config = Config::load_or_default()?;
     let server_path = config.get_server_path(name)?;
 
     // Determine if map is a URL or local path
-    if map.starts_with("http://") || map.starts_with("https://") {
+    let installed = if map.starts_with("http://") || map.starts_with("https://") {
         // Download from URL
         info!("Downloading map from URL: {}", map);
         match reqwest::blocking::get(map) {
@@ -187,7 +1707,7 @@ pub async fn install_map(name: &str, map: &str) -> Result<()> {
                         .with_context(|| "Failed to read map data from response")?;
 
                     // Extract filename from URL or use default
-                    let filename = map.split('/').last().unwrap_or("custom_map.bsp");
+                    let filename = map.split('/').next_back().unwrap_or("custom_map.bsp");
                     let maps_dir = server_path.join("game").join("csgo").join("maps");
 
                     std::fs::create_dir_all(&maps_dir).with_context(|| {
@@ -195,11 +1715,10 @@ pub async fn install_map(name: &str, map: &str) -> Result<()> {
                     })?;
 
                     let map_path = maps_dir.join(filename);
-                    std::fs::write(&map_path, map_data)
-                        .with_context(|| format!("Failed to write map file: {:?}", map_path))?;
+                    replace_map(&map_path, |file| file.write_all(&map_data))?;
 
                     info!("Map '{}' installed successfully", filename);
-                    println!("Map '{}' installed successfully", filename);
+                    map_path
                 } else {
                     error!("Failed to download map: HTTP {}", response.status());
                     anyhow::bail!("Failed to download map: HTTP {}", response.status());
@@ -210,6 +1729,50 @@ pub async fn install_map(name: &str, map: &str) -> Result<()> {
                 return Err(e.into());
             }
         }
+    } else if let Some(item) = map.strip_prefix("workshop:") {
+        let item_id: u64 = item
+            .parse()
+            .with_context(|| format!("Invalid Workshop item ID: {}", item))?;
+        let download_dir =
+            tempfile::tempdir().context("Failed to create Workshop download directory")?;
+        let login = SteamManager::steam_login(None)?;
+        let steam_manager = SteamManager::new()?.with_output(output);
+        let item_dir = steam_manager
+            .download_workshop_item(download_dir.path(), login, item_id)
+            .await?;
+
+        // A map item holds the map packed into a single .vpk
+        let mut packages = vec![];
+        for entry in std::fs::read_dir(&item_dir)
+            .with_context(|| format!("Failed to read Workshop item: {:?}", item_dir))?
+        {
+            let path = entry?.path();
+            if path.extension().is_some_and(|extension| extension == "vpk") {
+                packages.push(path);
+            }
+        }
+        let [source_path] = packages.as_slice() else {
+            anyhow::bail!(
+                "Workshop item {} has {} .vpk files, expected one map",
+                item_id,
+                packages.len()
+            );
+        };
+
+        let maps_dir = server_path.join("game").join("csgo").join("maps");
+        std::fs::create_dir_all(&maps_dir)
+            .with_context(|| format!("Failed to create maps directory: {:?}", maps_dir))?;
+        let filename = source_path
+            .file_name()
+            .with_context(|| "Invalid map filename")?;
+        let dest_path = maps_dir.join(filename);
+        replace_map(&dest_path, |file| copy_into(source_path, file))?;
+
+        info!(
+            "Workshop map '{}' installed successfully",
+            filename.to_string_lossy()
+        );
+        dest_path
     } else {
         // Assume local file path
         let source_path = Path::new(map);
@@ -226,24 +1789,63 @@ pub async fn install_map(name: &str, map: &str) -> Result<()> {
             .file_name()
             .with_context(|| "Invalid map filename")?;
         let dest_path = maps_dir.join(filename);
-
-        std::fs::copy(source_path, &dest_path)
-            .with_context(|| format!("Failed to copy map file to {:?}", dest_path))?;
+        replace_map(&dest_path, |file| copy_into(source_path, file))?;
 
         info!(
             "Map '{}' installed successfully",
             filename.to_string_lossy()
         );
-        println!(
-            "Map '{}' installed successfully",
-            filename.to_string_lossy()
-        );
+        dest_path
+    };
+
+    let filename = installed.file_name().unwrap_or_default().to_string_lossy();
+    if output.is_machine() {
+        output.emit(
+            "install_map",
+            &json!({ "name": name, "map": filename, "path": installed }),
+        )?;
+    } else {
+        println!("Map '{}' installed successfully", filename);
     }
 
     Ok(())
 }
 
-pub async fn install_plugin(server_name: &str, plugin: &str) -> Result<()> {
+/// Writes a map to a temporary file next to `dest` and renames it into place,
+/// so a failed download or copy leaves the old map intact. Renaming replaces
+/// rather than overwrites, as a stock map of an overlay is a hard link into
+/// the shared base; the replacement is the instance's own and survives base
+/// updates.
+fn replace_map(
+    dest: &Path,
+    write: impl FnOnce(&mut std::fs::File) -> std::io::Result<()>,
+) -> Result<()> {
+    let maps_dir = dest.parent().context("Invalid map path")?;
+    let mut temp = tempfile::Builder::new()
+        .prefix(".install-")
+        .tempfile_in(maps_dir)
+        .with_context(|| format!("Failed to create temporary map file in {:?}", maps_dir))?;
+    write(temp.as_file_mut())
+        .and_then(|_| temp.as_file().sync_all())
+        .with_context(|| format!("Failed to write map file: {:?}", dest))?;
+    // Temporary files are created readable by the owner only
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        temp.as_file()
+            .set_permissions(std::fs::Permissions::from_mode(0o644))
+            .with_context(|| format!("Failed to set permissions of map file: {:?}", dest))?;
+    }
+    temp.persist(dest)
+        .with_context(|| format!("Failed to replace map file: {:?}", dest))?;
+    Ok(())
+}
+
+fn copy_into(source: &Path, file: &mut std::fs::File) -> std::io::Result<()> {
+    std::io::copy(&mut std::fs::File::open(source)?, file).map(|_| ())
+}
+
+pub async fn install_plugin(server_name: &str, plugin: &str, output: OutputFormat) -> Result<()> {
     info!(
         "Installing plugin '{}' for server '{}'",
         plugin, server_name
@@ -316,8 +1918,17 @@ pub async fn install_plugin(server_name: &str, plugin: &str) -> Result<()> {
                 }
 
                 info!("Plugin '{}' installed successfully", plugin);
-                println!("Plugin '{}' installed successfully", plugin);
-                println!("Note: You may need to restart the server for the plugin to take effect.");
+                if output.is_machine() {
+                    output.emit(
+                        "plugin_install",
+                        &json!({ "name": server_name, "plugin": plugin, "url": plugin_url }),
+                    )?;
+                } else {
+                    println!("Plugin '{}' installed successfully", plugin);
+                    println!(
+                        "Note: You may need to restart the server for the plugin to take effect."
+                    );
+                }
             } else {
                 error!("Failed to download plugin: HTTP {}", response.status());
                 anyhow::bail!("Failed to download plugin: HTTP {}", response.status());
@@ -332,7 +1943,7 @@ pub async fn install_plugin(server_name: &str, plugin: &str) -> Result<()> {
     Ok(())
 }
 
-pub async fn list_plugins(server_name: &str) -> Result<()> {
+pub async fn list_plugins(server_name: &str, output: OutputFormat) -> Result<()> {
     info!("Listing plugins for server '{}'", server_name);
 
     let config = Config::load_or_default()?;
@@ -340,40 +1951,52 @@ pub async fn list_plugins(server_name: &str) -> Result<()> {
 
     let plugins_dir = server_path.join("game").join("csgo").join("addons");
 
-    if !plugins_dir.exists() {
-        info!("No plugins directory found for server '{}'", server_name);
-        println!("No plugins installed for server '{}'", server_name);
-        return Ok(());
-    }
-
-    match std::fs::read_dir(&plugins_dir) {
-        Ok(entries) => {
-            let mut plugins = Vec::new();
-            for entry in entries.flatten() {
-                if let Some(name) = entry.file_name().to_str() {
-                    plugins.push(name.to_string());
+    let mut plugins = Vec::new();
+    if plugins_dir.exists() {
+        match std::fs::read_dir(&plugins_dir) {
+            Ok(entries) => {
+                for entry in entries.flatten() {
+                    if let Some(name) = entry.file_name().to_str() {
+                        plugins.push(name.to_string());
+                    }
                 }
+                plugins.sort();
             }
-
-            if plugins.is_empty() {
-                println!("No plugins installed for server '{}'", server_name);
-            } else {
-                println!("Plugins for server '{}':", server_name);
-                for plugin in plugins {
-                    println!("- {}", plugin);
+            Err(e) => {
+                warn!("Failed to read plugins directory: {}", e);
+                if output.is_machine() {
+                    return Err(e).with_context(|| {
+                        format!("Failed to read plugins directory: {:?}", plugins_dir)
+                    });
                 }
+                println!("Error reading plugins directory: {}", e);
+                return Ok(());
             }
         }
-        Err(e) => {
-            warn!("Failed to read plugins directory: {}", e);
-            println!("Error reading plugins directory: {}", e);
+    } else {
+        info!("No plugins directory found for server '{}'", server_name);
+    }
+
+    if output.is_machine() {
+        return output.emit(
+            "plugin_list",
+            &json!({ "name": server_name, "plugins": plugins }),
+        );
+    }
+
+    if plugins.is_empty() {
+        println!("No plugins installed for server '{}'", server_name);
+    } else {
+        println!("Plugins for server '{}':", server_name);
+        for plugin in plugins {
+            println!("- {}", plugin);
         }
     }
 
     Ok(())
 }
 
-pub async fn remove_plugin(server_name: &str, plugin: &str) -> Result<()> {
+pub async fn remove_plugin(server_name: &str, plugin: &str, output: OutputFormat) -> Result<()> {
     info!("Removing plugin '{}' from server '{}'", plugin, server_name);
 
     let config = Config::load_or_default()?;
@@ -396,40 +2019,93 @@ pub async fn remove_plugin(server_name: &str, plugin: &str) -> Result<()> {
     }
 
     info!("Plugin '{}' removed successfully", plugin);
+    if output.is_machine() {
+        return output.emit(
+            "plugin_remove",
+            &json!({ "name": server_name, "plugin": plugin }),
+        );
+    }
     println!("Plugin '{}' removed successfully", plugin);
     println!("Note: You may need to restart the server for changes to take effect.");
 
     Ok(())
 }
 
-pub async fn show_recommended_plugins() -> Result<()> {
+pub async fn show_recommended_plugins(output: OutputFormat) -> Result<()> {
+    let recommended = [
+        ("SourceMod", "Server administration and plugin framework"),
+        ("MetaMod", "Plugin management system"),
+        ("SteamWorks", "Steam API integration"),
+        ("DHooks", "Dynamic hooks for Source engine"),
+        ("Accelerator", "Performance optimization"),
+    ];
+
+    if output.is_machine() {
+        let plugins: Vec<_> = recommended
+            .iter()
+            .map(|(name, description)| json!({ "name": name, "description": description }))
+            .collect();
+        return output.emit("plugin_recommended", &json!({ "plugins": plugins }));
+    }
+
     println!("Recommended CS2 plugins:");
-    println!("1. SourceMod - Server administration and plugin framework");
-    println!("2. MetaMod - Plugin management system");
-    println!("3. SteamWorks - Steam API integration");
-    println!("4. DHooks - Dynamic hooks for Source engine");
-    println!("5. Accelerator - Performance optimization");
+    for (i, (name, description)) in recommended.iter().enumerate() {
+        println!("{}. {} - {}", i + 1, name, description);
+    }
 
     Ok(())
 }
 
-pub async fn list_servers() -> Result<()> {
+pub async fn list_servers(output: OutputFormat) -> Result<()> {
     let config = Config::load_or_default()?;
     let servers = config.list_servers();
 
+    if output.is_machine() {
+        let servers = servers
+            .iter()
+            .map(|name| {
+                let instance = config.get_instance(name)?;
+                Ok(json!({
+                    "name": name,
+                    "path": instance.path,
+                    "port": instance.port,
+                    "tags": instance.tags,
+                    "owner": instance.owner,
+                    "autostart": instance.autostart,
+                }))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        return output.emit("server_list", &json!({ "servers": servers }));
+    }
+
     if servers.is_empty() {
         println!("No servers installed");
-    } else {
-        println!("Installed servers:");
-        for server in servers {
-            println!("- {}", server);
-        }
+        return Ok(());
     }
 
+    let rows = servers
+        .iter()
+        .map(|name| {
+            let instance = config.get_instance(name)?;
+            Ok(vec![
+                name.clone(),
+                instance.path.display().to_string(),
+                optional(instance.port),
+                match instance.tags.is_empty() {
+                    true => "-".to_string(),
+                    false => instance.tags.join(","),
+                },
+                optional(instance.owner.as_deref()),
+                if instance.autostart { "yes" } else { "no" }.to_string(),
+            ])
+        })
+        .collect::<Result<Vec<_>>>()?;
+    print_table(&["NAME", "PATH", "PORT", "TAGS", "OWNER", "AUTOSTART"], &rows);
+
     Ok(())
 }
 
-pub async fn backup_server(name: &str, backup_name: &str) -> Result<()> {
+pub async fn backup_server(name: &str, backup_name: &str, output: OutputFormat) -> Result<()> {
     info!("Creating backup '{}' for server '{}'", backup_name, name);
 
     let config = Config::load_or_default()?;
@@ -442,6 +2118,9 @@ pub async fn backup_server(name: &str, backup_name: &str) -> Result<()> {
     }
 
     info!("Backup '{}' created successfully", backup_name);
+    if output.is_machine() {
+        return output.emit("backup", &json!({ "name": name, "backup": backup_name }));
+    }
     println!(
         "Backup '{}' created successfully for server '{}'",
         backup_name, name
@@ -449,7 +2128,31 @@ pub async fn backup_server(name: &str, backup_name: &str) -> Result<()> {
     Ok(())
 }
 
-pub async fn restore_server(name: &str, backup_name: &str) -> Result<()> {
+pub async fn list_backups(name: &str, output: OutputFormat) -> Result<()> {
+    let config = Config::load_or_default()?;
+    let server_path = config.get_server_path(name)?;
+
+    let server_manager = ServerManager::new(server_path.clone());
+    let mut backups = server_manager.list_backups()?;
+    backups.sort();
+
+    if output.is_machine() {
+        return output.emit("backup_list", &json!({ "name": name, "backups": backups }));
+    }
+
+    if backups.is_empty() {
+        println!("No backups for server '{}'", name);
+    } else {
+        println!("Backups for server '{}':", name);
+        for backup in backups {
+            println!("- {}", backup);
+        }
+    }
+
+    Ok(())
+}
+
+pub async fn restore_server(name: &str, backup_name: &str, output: OutputFormat) -> Result<()> {
     info!("Restoring backup '{}' for server '{}'", backup_name, name);
 
     let config = Config::load_or_default()?;
@@ -462,6 +2165,9 @@ pub async fn restore_server(name: &str, backup_name: &str) -> Result<()> {
     }
 
     info!("Backup '{}' restored successfully", backup_name);
+    if output.is_machine() {
+        return output.emit("restore", &json!({ "name": name, "backup": backup_name }));
+    }
     println!(
         "Backup '{}' restored successfully for server '{}'",
         backup_name, name
@@ -470,16 +2176,129 @@ pub async fn restore_server(name: &str, backup_name: &str) -> Result<()> {
     Ok(())
 }
 
-pub async fn install_steamcmd() -> Result<()> {
+pub async fn install_steamcmd(output: OutputFormat) -> Result<()> {
     info!("Installing SteamCMD");
 
     if cfg!(target_os = "linux") {
         let path = crate::steam::SteamManager::install_steamcmd()
             .with_context(|| "Failed to install SteamCMD")?;
-        println!("SteamCMD installed successfully at {}", path);
+        if output.is_machine() {
+            output.emit("install_steamcmd", &json!({ "path": path }))?;
+        } else {
+            println!("SteamCMD installed successfully at {}", path);
+        }
     } else {
         anyhow::bail!("SteamCMD installation is only supported on Linux");
     }
 
     Ok(())
 }
+
+pub async fn rcon_command(name: &str, command: &str, output: OutputFormat) -> Result<()> {
+    info!("Sending RCON command to server '{}': {}", name, command);
+
+    let config = Config::load_or_default()?;
+    let server_path = config.get_server_path(name)?;
+
+    let target = RconTarget::for_instance(server_path)?;
+    let mut client = match RconClient::connect(&target, crate::rcon::DEFAULT_TIMEOUT).await {
+        Ok(client) => client,
+        Err(e) => {
+            error!("Failed to connect to server '{}' over RCON: {}", name, e);
+            return Err(e);
+        }
+    };
+
+    let response = client.exec(command).await?;
+    let response = response.trim_end();
+    if output.is_machine() {
+        return output.emit(
+            "rcon",
+            &json!({ "name": name, "command": command, "response": response }),
+        );
+    }
+    if !response.is_empty() {
+        println!("{}", response);
+    }
+
+    Ok(())
+}
+
+pub async fn open_console(name: &str) -> Result<()> {
+    info!("Opening RCON console for server '{}'", name);
+
+    let config = Config::load_or_default()?;
+    let server_path = config.get_server_path(name)?;
+
+    let mut console = Console::new(name, server_path)?;
+    if let Err(e) = console.run().await {
+        error!("RCON console for server '{}' failed: {}", name, e);
+        return Err(e);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn replaces_maps_in_place() {
+        let dir = tempfile::tempdir().unwrap();
+        let dest = dir.path().join("de_custom.vpk");
+        std::fs::write(&dest, "old").unwrap();
+
+        replace_map(&dest, |file| file.write_all(b"new")).unwrap();
+        assert_eq!(std::fs::read_to_string(&dest).unwrap(), "new");
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn failed_writes_keep_the_old_map() {
+        let dir = tempfile::tempdir().unwrap();
+        let dest = dir.path().join("de_custom.vpk");
+        std::fs::write(&dest, "old").unwrap();
+
+        let result = replace_map(&dest, |file| {
+            file.write_all(b"partial")?;
+            Err(std::io::Error::other("connection reset"))
+        });
+        assert!(result.is_err());
+        assert_eq!(std::fs::read_to_string(&dest).unwrap(), "old");
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 1);
+
+        let missing = dir.path().join("missing.vpk");
+        assert!(replace_map(&dest, |file| copy_into(&missing, file)).is_err());
+        assert_eq!(std::fs::read_to_string(&dest).unwrap(), "old");
+    }
+
+    #[test]
+    fn replacing_leaves_linked_base_files_alone() {
+        let dir = tempfile::tempdir().unwrap();
+        let base = dir.path().join("base.vpk");
+        let source = dir.path().join("source.vpk");
+        let maps_dir = dir.path().join("maps");
+        std::fs::create_dir(&maps_dir).unwrap();
+        let dest = maps_dir.join("de_dust2.vpk");
+        std::fs::write(&base, "stock").unwrap();
+        std::fs::write(&source, "custom").unwrap();
+        std::fs::hard_link(&base, &dest).unwrap();
+
+        replace_map(&dest, |file| copy_into(&source, file)).unwrap();
+        assert_eq!(std::fs::read_to_string(&dest).unwrap(), "custom");
+        assert_eq!(std::fs::read_to_string(&base).unwrap(), "stock");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn maps_are_readable() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let dest = dir.path().join("de_custom.vpk");
+        replace_map(&dest, |file| file.write_all(b"map")).unwrap();
+        let mode = std::fs::metadata(&dest).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o644);
+    }
+}
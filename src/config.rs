@@ -1,35 +1,257 @@
+use crate::cfgfile::{self, CfgDocument};
+use crate::cvars::{self, Issue, Severity};
+use crate::gamemode::GameMode;
+use crate::steamcmd::PUBLIC_BRANCH;
 use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use log::{info, warn};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::BTreeMap;
 use std::fs;
 use std::path::{Path, PathBuf};
 
-#[derive(Debug, Serialize, Deserialize, Default)]
+/// Cvar carrying the game server login token.
+pub const TOKEN_CVAR: &str = "sv_setsteamaccount";
+
+/// Version of the config.toml layout written by this build.
+pub const CONFIG_SCHEMA_VERSION: u32 = 2;
+
+#[derive(Debug, Serialize)]
 pub struct Config {
-    pub servers: HashMap<String, PathBuf>,
+    pub schema_version: u32,
+    pub servers: BTreeMap<String, InstanceConfig>,
+}
+
+/// Everything the tool records about one server instance.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InstanceConfig {
+    pub path: PathBuf,
+    /// Game port; falls back to hostport in server.cfg, then 27015
+    pub port: Option<u16>,
+    pub bind_ip: Option<String>,
+    /// Extra arguments passed to the server at launch, e.g. `-tickrate 128`
+    #[serde(default)]
+    pub launch_options: Vec<String>,
+    /// Game server login token
+    pub gslt: Option<String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// Steam beta branch, public when unset
+    pub branch: Option<String>,
+    /// Password of a private beta branch
+    pub branch_password: Option<String>,
+    /// Build the instance is held at instead of following its branch
+    pub pinned_build: Option<String>,
+    /// Depot manifests installed for the pinned build, manifest ID per depot ID
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub pinned_manifests: BTreeMap<String, String>,
+    /// Shared base install the game files are linked from, unset for a
+    /// standalone install
+    pub base: Option<PathBuf>,
+    pub owner: Option<String>,
+    #[serde(default)]
+    pub autostart: bool,
+    /// Unknown for instances migrated from older config files
+    pub created_at: Option<DateTime<Utc>>,
+    #[serde(default = "Utc::now")]
+    pub updated_at: DateTime<Utc>,
+}
+
+impl InstanceConfig {
+    pub fn new(path: PathBuf) -> Self {
+        let now = Utc::now();
+        Self {
+            path,
+            port: None,
+            bind_ip: None,
+            launch_options: vec![],
+            gslt: None,
+            tags: vec![],
+            branch: None,
+            branch_password: None,
+            pinned_build: None,
+            pinned_manifests: BTreeMap::new(),
+            base: None,
+            owner: None,
+            autostart: false,
+            created_at: Some(now),
+            updated_at: now,
+        }
+    }
+
+    /// Sets a field by name, an empty value clears it.
+    pub fn set(&mut self, field: &str, value: &str) -> Result<()> {
+        let optional = |value: &str| (!value.is_empty()).then(|| value.to_string());
+        match field {
+            "port" => {
+                self.port = match value {
+                    "" => None,
+                    _ => Some(value.parse().context("Invalid port")?),
+                }
+            }
+            "bind_ip" => {
+                if !value.is_empty() {
+                    value
+                        .parse::<std::net::IpAddr>()
+                        .context("Invalid bind IP address")?;
+                }
+                self.bind_ip = optional(value);
+            }
+            "launch_options" => {
+                self.launch_options = value.split_whitespace().map(String::from).collect()
+            }
+            "tags" => {
+                self.tags = value
+                    .split(',')
+                    .map(|tag| tag.trim().to_string())
+                    .filter(|tag| !tag.is_empty())
+                    .collect()
+            }
+            "branch" => self.set_branch(optional(value)),
+            "branch_password" => self.branch_password = optional(value),
+            "owner" => self.owner = optional(value),
+            "autostart" => {
+                self.autostart = match value {
+                    "true" | "1" | "yes" => true,
+                    "false" | "0" | "no" | "" => false,
+                    _ => anyhow::bail!("Invalid autostart value: {}", value),
+                }
+            }
+            _ => anyhow::bail!(
+                "Unknown instance field '{}' (expected one of: {})",
+                field,
+                INSTANCE_FIELDS.join(", ")
+            ),
+        }
+        self.touch();
+        Ok(())
+    }
+
+    pub fn touch(&mut self) {
+        self.updated_at = Utc::now();
+    }
+
+    /// Switches the Steam branch, `None` or `public` for the public one. An
+    /// overlay also needs its base moved to the new branch, see
+    /// [`InstanceConfig::branch_name`].
+    pub fn set_branch(&mut self, branch: Option<String>) {
+        self.branch = branch.filter(|branch| branch != PUBLIC_BRANCH);
+    }
+
+    /// Name of the Steam branch the instance follows.
+    pub fn branch_name(&self) -> &str {
+        self.branch.as_deref().unwrap_or(PUBLIC_BRANCH)
+    }
+
+    pub fn is_pinned(&self) -> bool {
+        !self.pinned_manifests.is_empty()
+    }
+}
+
+pub const INSTANCE_FIELDS: &[&str] = &[
+    "port",
+    "bind_ip",
+    "launch_options",
+    "tags",
+    "branch",
+    "branch_password",
+    "owner",
+    "autostart",
+];
+
+/// config.toml as found on disk, possibly written by an older version.
+#[derive(Deserialize)]
+struct StoredConfig {
+    #[serde(default)]
+    schema_version: u32,
+    #[serde(default)]
+    servers: BTreeMap<String, StoredInstance>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum StoredInstance {
+    Full(Box<InstanceConfig>),
+    /// Files without a schema version mapped names straight to paths
+    Path(PathBuf),
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            schema_version: CONFIG_SCHEMA_VERSION,
+            servers: BTreeMap::new(),
+        }
+    }
 }
 
 impl Config {
     pub fn load_or_default() -> Result<Self> {
-        let config_path = Self::config_path();
-        if config_path.exists() {
-            let content = fs::read_to_string(&config_path)
-                .with_context(|| format!("Failed to read config file: {:?}", config_path))?;
-            let config: Config = toml::from_str(&content)
-                .with_context(|| format!("Failed to parse config file: {:?}", config_path))?;
-            Ok(config)
-        } else {
-            Ok(Config::default())
+        Self::load_from(&Self::config_path())
+    }
+
+    /// Loads a config file, migrating one of an older schema in place.
+    fn load_from(config_path: &Path) -> Result<Self> {
+        if !config_path.exists() {
+            return Ok(Config::default());
+        }
+
+        let content = fs::read_to_string(config_path)
+            .with_context(|| format!("Failed to read config file: {:?}", config_path))?;
+        let stored: StoredConfig = toml::from_str(&content)
+            .with_context(|| format!("Failed to parse config file: {:?}", config_path))?;
+
+        if stored.schema_version > CONFIG_SCHEMA_VERSION {
+            anyhow::bail!(
+                "{:?} was written by a newer version of cs2-server-cli (schema {}, supported {})",
+                config_path,
+                stored.schema_version,
+                CONFIG_SCHEMA_VERSION
+            );
         }
+
+        let migrate = stored.schema_version < CONFIG_SCHEMA_VERSION;
+        let config = Config {
+            schema_version: CONFIG_SCHEMA_VERSION,
+            servers: stored
+                .servers
+                .into_iter()
+                .map(|(name, instance)| {
+                    let instance = match instance {
+                        StoredInstance::Full(instance) => *instance,
+                        StoredInstance::Path(path) => InstanceConfig {
+                            created_at: None,
+                            ..InstanceConfig::new(path)
+                        },
+                    };
+                    (name, instance)
+                })
+                .collect(),
+        };
+
+        if migrate {
+            let backup = config_path.with_extension(format!("toml.v{}", stored.schema_version));
+            fs::copy(config_path, &backup)
+                .with_context(|| format!("Failed to back up config file to {:?}", backup))?;
+            config.save_to(config_path)?;
+            info!(
+                "Migrated {:?} from schema {} to {}, previous file kept at {:?}",
+                config_path, stored.schema_version, CONFIG_SCHEMA_VERSION, backup
+            );
+        }
+        Ok(config)
     }
 
     pub fn save(&self) -> Result<()> {
-        let config_path = Self::config_path();
+        self.save_to(&Self::config_path())
+    }
+
+    fn save_to(&self, config_path: &Path) -> Result<()> {
         let content = toml::to_string(self)
             .context("Failed to serialize config")?;
         fs::create_dir_all(config_path.parent().unwrap())
             .with_context(|| format!("Failed to create config directory: {:?}", config_path.parent()))?;
-        fs::write(&config_path, content)
+        write_private(config_path, content.as_bytes())
             .with_context(|| format!("Failed to write config file: {:?}", config_path))?;
         Ok(())
     }
@@ -38,48 +260,76 @@ impl Config {
         if self.servers.contains_key(&name) {
             anyhow::bail!("Server '{}' already exists", name);
         }
-        self.servers.insert(name, path);
+        self.servers.insert(name, InstanceConfig::new(path));
         Ok(())
     }
 
     pub fn get_server_path(&self, name: &str) -> Result<&PathBuf> {
+        Ok(&self.get_instance(name)?.path)
+    }
+
+    pub fn get_instance(&self, name: &str) -> Result<&InstanceConfig> {
         self.servers.get(name)
             .with_context(|| format!("Server '{}' not found", name))
     }
 
+    pub fn get_instance_mut(&mut self, name: &str) -> Result<&mut InstanceConfig> {
+        self.servers.get_mut(name)
+            .with_context(|| format!("Server '{}' not found", name))
+    }
+
     pub fn list_servers(&self) -> Vec<String> {
         self.servers.keys().cloned().collect()
     }
 
-    fn config_path() -> PathBuf {
+    /// Directory holding config.toml, defaults.toml and profiles.
+    pub fn config_dir() -> PathBuf {
         dirs::config_dir()
             .unwrap_or_else(|| PathBuf::from("."))
             .join("cs2-server-cli")
-            .join("config.toml")
+    }
+
+    fn config_path() -> PathBuf {
+        Self::config_dir().join("config.toml")
     }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+/// Writes a file only its owner can read, as config.toml holds login tokens
+/// and branch passwords. An existing file is restricted before the new
+/// content goes in.
+fn write_private(path: &Path, content: &[u8]) -> std::io::Result<()> {
+    use std::io::Write;
+
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    let mut file = options.open(path)?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        file.set_permissions(fs::Permissions::from_mode(0o600))?;
+    }
+    file.write_all(content)
+}
+
+#[derive(Debug, Clone)]
 pub struct ServerConfig {
-    pub hostname: String,
-    pub rcon_password: String,
-    pub sv_password: String,
-    pub maxplayers: u32,
-    pub map: String,
-    pub game_mode: String,
-    pub game_type: String,
+    document: CfgDocument,
 }
 
 impl Default for ServerConfig {
     fn default() -> Self {
+        let content = "// CS2 Server Configuration\n\
+                       hostname \"CS2 Server\"\n\
+                       rcon_password \"changeme\"\n\
+                       sv_password \"\"\n\
+                       maxplayers 10\n\
+                       map de_dust2\n\
+                       game_mode 0\n\
+                       game_type 0\n";
         Self {
-            hostname: "CS2 Server".to_string(),
-            rcon_password: "changeme".to_string(),
-            sv_password: "".to_string(),
-            maxplayers: 10,
-            map: "de_dust2".to_string(),
-            game_mode: "0".to_string(),
-            game_type: "0".to_string(),
+            document: CfgDocument::parse(content),
         }
     }
 }
@@ -88,84 +338,234 @@ impl ServerConfig {
     pub fn load(path: &Path) -> Result<Self> {
         let content = fs::read_to_string(path)
             .with_context(|| format!("Failed to read server config: {:?}", path))?;
-        Self::parse_from_cfg(&content)
+        let document = CfgDocument::parse(&content);
+        for error in document.errors() {
+            warn!("Ignoring invalid line in {:?}: {}", path, error);
+        }
+        Ok(Self { document })
+    }
+
+    /// Looks up a single cvar in a cfg file.
+    pub fn lookup(path: &Path, key: &str) -> Result<Option<String>> {
+        Ok(Self::load(path)?.get(key).map(|value| value.to_string()))
     }
 
     pub fn save(&self, path: &Path) -> Result<()> {
-        let content = self.to_cfg_string();
+        let content = self.document.render();
         fs::write(path, content)
             .with_context(|| format!("Failed to write server config: {:?}", path))?;
         Ok(())
     }
 
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.document.get(key)
+    }
+
     pub fn set(&mut self, key: &str, value: &str) -> Result<()> {
-        match key {
-            "hostname" => self.hostname = value.to_string(),
-            "rcon_password" => self.rcon_password = value.to_string(),
-            "sv_password" => self.sv_password = value.to_string(),
-            "maxplayers" => self.maxplayers = value.parse().context("Invalid maxplayers value")?,
-            "map" => self.map = value.to_string(),
-            "game_mode" => self.game_mode = value.to_string(),
-            "game_type" => self.game_type = value.to_string(),
-            _ => anyhow::bail!("Unknown configuration key: {}", key),
+        if key.is_empty() || key.contains(char::is_whitespace) || key.contains('"') {
+            anyhow::bail!("Invalid cvar name: {:?}", key);
         }
+        if cfgfile::is_command(key) {
+            anyhow::bail!("'{}' is a command, not a setting", key);
+        }
+        if key.eq_ignore_ascii_case(TOKEN_CVAR) {
+            anyhow::bail!(
+                "The login token is kept out of server.cfg, use `cs2-server-cli token set` instead"
+            );
+        }
+        self.document.set(key, value);
         Ok(())
     }
 
-    fn to_cfg_string(&self) -> String {
-        format!(
-            "// CS2 Server Configuration\n\
-             hostname \"{}\"\n\
-             rcon_password \"{}\"\n\
-             sv_password \"{}\"\n\
-             maxplayers {}\n\
-             map {}\n\
-             game_mode {}\n\
-             game_type {}\n",
-            self.hostname, self.rcon_password, self.sv_password, self.maxplayers,
-            self.map, self.game_mode, self.game_type
-        )
+    /// Removes a cvar. Returns false if it was not set.
+    pub fn unset(&mut self, key: &str) -> bool {
+        self.document.unset(key)
     }
 
-    fn parse_from_cfg(content: &str) -> Result<Self> {
-        let mut config = Self::default();
+    /// Checks the file for syntax errors and values the cvar schema rejects.
+    pub fn validate(&self) -> Vec<Issue> {
+        let mut issues: Vec<Issue> = self
+            .document
+            .errors()
+            .iter()
+            .map(|error| Issue {
+                severity: Severity::Error,
+                line: Some(error.line),
+                key: None,
+                message: format!("column {}: {}", error.column, error.message),
+            })
+            .collect();
 
-        for line in content.lines() {
-            let line = line.trim();
-            if line.starts_with("//") || line.is_empty() {
-                continue;
+        let schema = cvars::schema();
+        for (line, key, value) in self.document.assignments() {
+            if let Err(e) = schema.check(key, value) {
+                issues.push(Issue {
+                    severity: Severity::Error,
+                    line: Some(line),
+                    key: Some(key.to_string()),
+                    message: format!("{:#}", e),
+                });
             }
-
-            if let Some((key, value)) = Self::parse_cfg_line(line) {
-                config.set(&key, &value)?;
+            // A misspelled cvar is silently ignored by the game, not fatal
+            if let Some(warning) = schema.typo_warning(key) {
+                issues.push(Issue {
+                    severity: Severity::Warning,
+                    line: Some(line),
+                    key: Some(key.to_string()),
+                    message: warning,
+                });
             }
         }
+        issues.sort_by_key(|issue| issue.line);
+        issues
+    }
 
-        Ok(config)
+    /// The preset matching the configured game_type and game_mode, if any.
+    pub fn game_mode(&self) -> Option<GameMode> {
+        GameMode::detect(
+            self.get("game_type").unwrap_or("0"),
+            self.get("game_mode").unwrap_or("0"),
+            self.get("mapgroup"),
+        )
     }
 
-    fn parse_cfg_line(line: &str) -> Option<(String, String)> {
-        let line = line.trim();
-        if line.contains('"') {
-            // Handle quoted values
-            let parts: Vec<&str> = line.split('"').collect();
-            if parts.len() >= 3 {
-                let key = parts[0].trim().to_string();
-                let value = parts[1].to_string();
-                Some((key, value))
-            } else {
-                None
-            }
-        } else {
-            // Handle unquoted values
-            let parts: Vec<&str> = line.split_whitespace().collect();
-            if parts.len() >= 2 {
-                let key = parts[0].to_string();
-                let value = parts[1].to_string();
-                Some((key, value))
-            } else {
-                None
-            }
-        }
+    /// Writes the game_type, game_mode, maxplayers and mapgroup of a preset.
+    pub fn apply_mode(&mut self, mode: GameMode) -> Result<()> {
+        let preset = mode.preset();
+        self.set("game_type", &preset.game_type.to_string())?;
+        self.set("game_mode", &preset.game_mode.to_string())?;
+        self.set("maxplayers", &preset.maxplayers.to_string())?;
+        self.set("mapgroup", preset.mapgroup)?;
+        Ok(())
+    }
+
+    /// Effective cvar values in file order.
+    pub fn cvars(&self) -> Vec<(String, String)> {
+        self.document.cvars()
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stored(path: &Path) -> toml::Value {
+        toml::from_str(&fs::read_to_string(path).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn migrates_path_maps() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        let original = "[servers]\na = \"/srv/cs2/a\"\nb = \"/srv/cs2/b\"\n";
+        fs::write(&path, original).unwrap();
+
+        let config = Config::load_from(&path).unwrap();
+        assert_eq!(config.schema_version, CONFIG_SCHEMA_VERSION);
+        assert_eq!(config.list_servers(), ["a", "b"]);
+        let a = config.get_instance("a").unwrap();
+        assert_eq!(a.path, PathBuf::from("/srv/cs2/a"));
+        assert_eq!(a.created_at, None);
+        assert_eq!(a.branch_name(), PUBLIC_BRANCH);
+
+        assert_eq!(
+            fs::read_to_string(dir.path().join("config.toml.v0")).unwrap(),
+            original
+        );
+        let migrated = stored(&path);
+        assert_eq!(
+            migrated["schema_version"].as_integer(),
+            Some(CONFIG_SCHEMA_VERSION as i64)
+        );
+        assert_eq!(
+            migrated["servers"]["b"]["path"].as_str(),
+            Some("/srv/cs2/b")
+        );
+
+        // Loading the migrated file changes nothing
+        let again = Config::load_from(&path).unwrap();
+        assert_eq!(again.get_instance("a").unwrap().path, a.path);
+        assert!(!dir.path().join("config.toml.v2").exists());
+    }
+
+    #[test]
+    fn migrates_instance_tables() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        let original = "schema_version = 1\n\n\
+                        [servers.a]\n\
+                        path = \"/srv/cs2/a\"\n\
+                        port = 27016\n\
+                        tags = [\"eu\"]\n\
+                        created_at = \"2026-01-02T03:04:05Z\"\n\
+                        updated_at = \"2026-01-02T03:04:05Z\"\n";
+        fs::write(&path, original).unwrap();
+
+        let config = Config::load_from(&path).unwrap();
+        let a = config.get_instance("a").unwrap();
+        assert_eq!(a.port, Some(27016));
+        assert_eq!(a.tags, ["eu"]);
+        assert_eq!(
+            a.created_at.map(|at| at.to_rfc3339()).as_deref(),
+            Some("2026-01-02T03:04:05+00:00")
+        );
+
+        assert_eq!(
+            fs::read_to_string(dir.path().join("config.toml.v1")).unwrap(),
+            original
+        );
+        assert_eq!(
+            stored(&path)["schema_version"].as_integer(),
+            Some(CONFIG_SCHEMA_VERSION as i64)
+        );
+    }
+
+    #[test]
+    fn leaves_current_files_alone() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        let original = format!(
+            "schema_version = {}\n\n[servers.a]\npath = \"/srv/cs2/a\"\n",
+            CONFIG_SCHEMA_VERSION
+        );
+        fs::write(&path, &original).unwrap();
+
+        Config::load_from(&path).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), original);
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn rejects_newer_schemas() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        fs::write(&path, "schema_version = 99\n").unwrap();
+
+        let error = Config::load_from(&path).unwrap_err();
+        assert!(error.to_string().contains("newer version"));
+        assert_eq!(fs::read_to_string(&path).unwrap(), "schema_version = 99\n");
+    }
+
+    #[test]
+    fn missing_file_is_empty() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = Config::load_from(&dir.path().join("config.toml")).unwrap();
+        assert!(config.servers.is_empty());
+        assert!(fs::read_dir(dir.path()).unwrap().next().is_none());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn saves_privately() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        fs::write(&path, "[servers]\na = \"/srv/cs2/a\"\n").unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o644)).unwrap();
+
+        Config::load_from(&path).unwrap();
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+    }
+}
@@ -0,0 +1,380 @@
+use anyhow::{Context, Result};
+use log::{info, warn};
+use rustyline::completion::{Completer, Pair};
+use rustyline::error::ReadlineError;
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::history::DefaultHistory;
+use rustyline::validate::Validator;
+use rustyline::{Editor, Helper};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::cvars;
+use crate::rcon::{RconClient, RconTarget, DEFAULT_TIMEOUT};
+
+const HISTORY_FILE: &str = ".console_history";
+const HISTORY_SIZE: usize = 1000;
+const RECONNECT_ATTEMPTS: u32 = 3;
+
+// Frequently used console commands and cvars offered for completion
+const COMMON_COMMANDS: &[&str] = &[
+    "bot_add",
+    "bot_add_ct",
+    "bot_add_t",
+    "bot_difficulty",
+    "bot_kick",
+    "bot_quota",
+    "bot_quota_mode",
+    "changelevel",
+    "ds_workshop_changelevel",
+    "echo",
+    "exec",
+    "game_mode",
+    "game_type",
+    "host_workshop_collection",
+    "host_workshop_map",
+    "hostname",
+    "kick",
+    "kickid",
+    "listid",
+    "map",
+    "mapgroup",
+    "maps",
+    "mp_autoteambalance",
+    "mp_buytime",
+    "mp_c4timer",
+    "mp_freezetime",
+    "mp_friendlyfire",
+    "mp_halftime",
+    "mp_limitteams",
+    "mp_maxmoney",
+    "mp_maxrounds",
+    "mp_overtime_enable",
+    "mp_pause_match",
+    "mp_restartgame",
+    "mp_roundtime",
+    "mp_roundtime_defuse",
+    "mp_startmoney",
+    "mp_timelimit",
+    "mp_unpause_match",
+    "mp_warmup_end",
+    "mp_warmup_start",
+    "mp_warmuptime",
+    "quit",
+    "rcon_password",
+    "say",
+    "status",
+    "sv_cheats",
+    "sv_lan",
+    "sv_password",
+    "sv_visiblemaxplayers",
+    "tv_enable",
+    "tv_record",
+    "tv_stoprecord",
+    "users",
+];
+
+// Stock maps offered as arguments to map changing commands
+const STOCK_MAPS: &[&str] = &[
+    "cs_italy",
+    "cs_office",
+    "de_ancient",
+    "de_anubis",
+    "de_dust2",
+    "de_inferno",
+    "de_mirage",
+    "de_nuke",
+    "de_overpass",
+    "de_train",
+    "de_vertigo",
+];
+
+const MAP_COMMANDS: &[&str] = &["changelevel", "map"];
+
+struct ConsoleHelper {
+    maps: Vec<String>,
+}
+
+impl ConsoleHelper {
+    fn new(server_path: &Path) -> Self {
+        let mut maps: Vec<String> = STOCK_MAPS.iter().map(|m| m.to_string()).collect();
+
+        // Include custom maps installed with install-map
+        let maps_dir = server_path.join("game").join("csgo").join("maps");
+        if let Ok(entries) = std::fs::read_dir(maps_dir) {
+            for entry in entries.flatten() {
+                let path = entry.path();
+                let is_map = path
+                    .extension()
+                    .is_some_and(|ext| ext == "vpk" || ext == "bsp");
+                if let (true, Some(stem)) = (is_map, path.file_stem().and_then(|s| s.to_str())) {
+                    maps.push(stem.to_string());
+                }
+            }
+        }
+
+        maps.sort();
+        maps.dedup();
+        Self { maps }
+    }
+}
+
+impl Completer for ConsoleHelper {
+    type Candidate = Pair;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _ctx: &rustyline::Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<Pair>)> {
+        let before_cursor = &line[..pos];
+        let start = before_cursor
+            .rfind(|c: char| c.is_whitespace() || c == ';')
+            .map(|i| i + 1)
+            .unwrap_or(0);
+        let word = &before_cursor[start..];
+
+        // Complete map names after a map changing command, commands otherwise
+        let command = before_cursor[..start].split_whitespace().last();
+        let candidates: Vec<&str> = match command {
+            Some(command) if MAP_COMMANDS.contains(&command) => {
+                self.maps.iter().map(|m| m.as_str()).collect()
+            }
+            Some(_) if !before_cursor[..start].trim_end().ends_with(';') => vec![],
+            _ => COMMON_COMMANDS.to_vec(),
+        };
+
+        let matches = candidates
+            .into_iter()
+            .filter(|candidate| candidate.starts_with(word))
+            .map(|candidate| Pair {
+                display: candidate.to_string(),
+                replacement: candidate.to_string(),
+            })
+            .collect();
+
+        Ok((start, matches))
+    }
+}
+
+impl Hinter for ConsoleHelper {
+    type Hint = String;
+}
+
+impl Highlighter for ConsoleHelper {}
+
+impl Validator for ConsoleHelper {}
+
+impl Helper for ConsoleHelper {}
+
+/// Interactive RCON session against a running instance.
+pub struct Console {
+    name: String,
+    target: RconTarget,
+    history_path: PathBuf,
+    editor: Editor<ConsoleHelper, DefaultHistory>,
+    client: Option<RconClient>,
+}
+
+impl Console {
+    pub fn new(name: &str, server_path: &Path) -> Result<Self> {
+        let target = RconTarget::for_instance(server_path)?;
+
+        let config = rustyline::Config::builder()
+            .max_history_size(HISTORY_SIZE)
+            .context("Invalid history size")?
+            .auto_add_history(false)
+            .build();
+        let mut editor = Editor::with_config(config).context("Failed to initialize line editor")?;
+        editor.set_helper(Some(ConsoleHelper::new(server_path)));
+
+        let history_path = server_path.join(HISTORY_FILE);
+        if history_path.exists() {
+            if let Err(e) = editor.load_history(&history_path) {
+                warn!("Failed to load console history {:?}: {}", history_path, e);
+            }
+        }
+        if let Err(e) = create_private(&history_path) {
+            warn!(
+                "Failed to restrict console history {:?}: {}",
+                history_path, e
+            );
+        }
+
+        Ok(Self {
+            name: name.to_string(),
+            target,
+            history_path,
+            editor,
+            client: None,
+        })
+    }
+
+    pub async fn run(&mut self) -> Result<()> {
+        self.client = Some(RconClient::connect(&self.target, DEFAULT_TIMEOUT).await?);
+        println!(
+            "Connected to '{}' at {}. Type .exit or press Ctrl+D to leave.",
+            self.name,
+            self.target.address()
+        );
+
+        let prompt = format!("{}> ", self.name);
+        loop {
+            let line = match self.editor.readline(&prompt) {
+                Ok(line) => line,
+                Err(ReadlineError::Interrupted) => continue,
+                Err(ReadlineError::Eof) => break,
+                Err(e) => return Err(e).context("Failed to read console input"),
+            };
+
+            let command = line.trim();
+            if command.is_empty() {
+                continue;
+            }
+            if !is_secret(command) {
+                if let Err(e) = self.editor.add_history_entry(command) {
+                    warn!("Failed to record history entry: {}", e);
+                }
+                // Written right away, so it survives however the session ends
+                if let Err(e) = self.editor.append_history(&self.history_path) {
+                    warn!(
+                        "Failed to save console history {:?}: {}",
+                        self.history_path, e
+                    );
+                }
+            }
+
+            match command {
+                ".exit" => break,
+                ".reconnect" => {
+                    self.client = None;
+                    if let Err(e) = self.reconnect().await {
+                        eprintln!("{}", e);
+                    }
+                    continue;
+                }
+                _ => {}
+            }
+
+            match self.exec(command).await {
+                Ok(response) => {
+                    let response = response.trim_end();
+                    if !response.is_empty() {
+                        println!("{}", response);
+                    }
+                }
+                Err(e) => eprintln!("Error: {:#}", e),
+            }
+        }
+        Ok(())
+    }
+
+    /// Executes a command, reconnecting first if the connection was dropped.
+    /// A command that fails mid-flight is not resent since it may have run already.
+    async fn exec(&mut self, command: &str) -> Result<String> {
+        if self.client.is_none() {
+            self.reconnect().await?;
+        }
+
+        let client = self.client.as_mut().context("Not connected")?;
+        match client.exec(command).await {
+            Ok(response) => Ok(response),
+            Err(e) => {
+                warn!("RCON connection lost: {}", e);
+                self.client = None;
+                if let Err(reconnect_error) = self.reconnect().await {
+                    eprintln!("{}", reconnect_error);
+                }
+                Err(e).context("Connection lost, the command may not have been executed")
+            }
+        }
+    }
+
+    async fn reconnect(&mut self) -> Result<()> {
+        for attempt in 1..=RECONNECT_ATTEMPTS {
+            eprintln!(
+                "Reconnecting to {} (attempt {}/{})...",
+                self.target.address(),
+                attempt,
+                RECONNECT_ATTEMPTS
+            );
+            match RconClient::connect(&self.target, DEFAULT_TIMEOUT).await {
+                Ok(client) => {
+                    info!("Reconnected to '{}'", self.name);
+                    self.client = Some(client);
+                    return Ok(());
+                }
+                Err(e) => {
+                    warn!("Reconnect attempt {} failed: {}", attempt, e);
+                    tokio::time::sleep(Duration::from_secs(attempt as u64)).await;
+                }
+            }
+        }
+
+        anyhow::bail!(
+            "Could not reconnect to '{}' after {} attempts, use .reconnect to retry",
+            self.name,
+            RECONNECT_ATTEMPTS
+        )
+    }
+}
+
+/// Whether the command sets a protected cvar such as `rcon_password`, which
+/// is kept out of the history.
+fn is_secret(command: &str) -> bool {
+    command
+        .split_whitespace()
+        .next()
+        .is_some_and(|name| cvars::schema().is_protected(name))
+}
+
+/// Creates the file readable by its owner only, or restricts an existing one;
+/// the history holds every command sent to the server.
+fn create_private(path: &Path) -> std::io::Result<()> {
+    let mut options = std::fs::OpenOptions::new();
+    options.append(true).create(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    options.open(path)?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o600))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_secrets_out_of_history() {
+        assert!(is_secret("rcon_password hunter2"));
+        assert!(is_secret("  sv_password \"hunter2\""));
+        assert!(is_secret("SV_SETSTEAMACCOUNT 0123456789ABCDEF"));
+        assert!(!is_secret("status"));
+        assert!(!is_secret("say rcon_password"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn history_is_private() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(HISTORY_FILE);
+        create_private(&path).unwrap();
+        let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o644)).unwrap();
+        std::fs::write(&path, "status\n").unwrap();
+        create_private(&path).unwrap();
+        let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "status\n");
+    }
+}
@@ -0,0 +1,250 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+const SCHEMA: &str = include_str!("cvars.toml");
+
+// Misspellings further away than this get no suggestion
+const MAX_SUGGESTION_DISTANCE: usize = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CvarType {
+    Int,
+    Float,
+    Bool,
+    String,
+}
+
+/// What the bundled schema knows about a cvar.
+#[derive(Debug, Clone, Deserialize)]
+pub struct CvarSpec {
+    #[serde(rename = "type")]
+    pub kind: CvarType,
+    pub min: Option<f64>,
+    pub max: Option<f64>,
+    #[serde(default)]
+    pub values: Vec<String>,
+    pub description: String,
+    #[serde(default)]
+    pub protected: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+/// A problem found while validating a cfg file.
+#[derive(Debug, Clone, Serialize)]
+pub struct Issue {
+    pub severity: Severity,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub line: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub key: Option<String>,
+    pub message: String,
+}
+
+pub struct CvarSchema {
+    cvars: HashMap<String, CvarSpec>,
+}
+
+/// The schema bundled with the binary.
+pub fn schema() -> &'static CvarSchema {
+    static SCHEMA_CELL: OnceLock<CvarSchema> = OnceLock::new();
+    SCHEMA_CELL.get_or_init(|| {
+        let cvars = toml::from_str(SCHEMA).expect("bundled cvar schema is valid");
+        CvarSchema { cvars }
+    })
+}
+
+impl CvarSchema {
+    pub fn get(&self, name: &str) -> Option<&CvarSpec> {
+        self.cvars.get(&name.to_ascii_lowercase())
+    }
+
+    pub fn is_protected(&self, name: &str) -> bool {
+        self.get(name).is_some_and(|spec| spec.protected)
+    }
+
+    /// Closest known cvar name for a misspelled one.
+    pub fn suggest(&self, name: &str) -> Option<&str> {
+        let name = name.to_ascii_lowercase();
+        self.cvars
+            .keys()
+            .map(|known| (levenshtein(&name, known), known))
+            .filter(|(distance, _)| *distance <= MAX_SUGGESTION_DISTANCE.min(name.len() / 2))
+            .min()
+            .map(|(_, known)| known.as_str())
+    }
+
+    /// Checks a value against the schema. Cvars the schema does not know are
+    /// accepted, it lists only a fraction of them.
+    pub fn check(&self, name: &str, value: &str) -> Result<()> {
+        match self.get(name) {
+            Some(spec) => spec
+                .check(value)
+                .with_context(|| format!("Invalid value for {}", name)),
+            None => Ok(()),
+        }
+    }
+
+    /// A warning for a cvar the schema does not know but that looks like a
+    /// typo of one it does.
+    pub fn typo_warning(&self, name: &str) -> Option<String> {
+        if self.get(name).is_some() {
+            return None;
+        }
+        let suggestion = self.suggest(name)?;
+        Some(format!(
+            "Unknown cvar '{}', did you mean '{}'?",
+            name, suggestion
+        ))
+    }
+}
+
+impl CvarSpec {
+    pub fn check(&self, value: &str) -> Result<()> {
+        let number = match self.kind {
+            CvarType::Int => Some(
+                value
+                    .parse::<i64>()
+                    .with_context(|| format!("'{}' is not an integer", value))? as f64,
+            ),
+            CvarType::Float => Some(
+                value
+                    .parse::<f64>()
+                    .ok()
+                    .filter(|number| number.is_finite())
+                    .with_context(|| format!("'{}' is not a number", value))?,
+            ),
+            CvarType::Bool => {
+                if value != "0" && value != "1" {
+                    anyhow::bail!("'{}' is not 0 or 1", value);
+                }
+                None
+            }
+            CvarType::String => None,
+        };
+
+        if !self.values.is_empty() && !self.values.iter().any(|allowed| allowed == value) {
+            anyhow::bail!(
+                "'{}' is not one of: {}",
+                value,
+                self.values.join(", ")
+            );
+        }
+
+        if let Some(number) = number {
+            if let Some(min) = self.min.filter(|min| number < *min) {
+                anyhow::bail!("{} is below the minimum of {}", value, min);
+            }
+            if let Some(max) = self.max.filter(|max| number > *max) {
+                anyhow::bail!("{} is above the maximum of {}", value, max);
+            }
+        }
+        Ok(())
+    }
+}
+
+fn levenshtein(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+
+    for (i, ca) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != *cb);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn levenshtein_distances() {
+        assert_eq!(levenshtein("", ""), 0);
+        assert_eq!(levenshtein("abc", ""), 3);
+        assert_eq!(levenshtein("", "abc"), 3);
+        assert_eq!(levenshtein("hostname", "hostname"), 0);
+        assert_eq!(levenshtein("hostnme", "hostname"), 1);
+        assert_eq!(levenshtein("hostanme", "hostname"), 2);
+        assert_eq!(levenshtein("kitten", "sitting"), 3);
+        assert_eq!(levenshtein("sv_mincmdrate", "sv_minrate"), 3);
+    }
+
+    #[test]
+    fn suggests_close_names_only() {
+        let schema = schema();
+        assert_eq!(schema.suggest("hostnme"), Some("hostname"));
+        assert_eq!(schema.suggest("MAXPLAYER"), Some("maxplayers"));
+        assert_eq!(schema.suggest("completely_different"), None);
+        // Short names need a closer match
+        assert_eq!(schema.suggest("xy"), None);
+    }
+
+    #[test]
+    fn unknown_cvars_are_accepted() {
+        let schema = schema();
+        for name in ["sv_mincmdrate", "sv_maxcmdrate", "hostnme", "mp_made_up"] {
+            assert!(schema.check(name, "128").is_ok(), "{}", name);
+        }
+        assert_eq!(
+            schema.typo_warning("hostnme").as_deref(),
+            Some("Unknown cvar 'hostnme', did you mean 'hostname'?")
+        );
+        assert!(schema.typo_warning("hostname").is_none());
+        assert!(schema.typo_warning("mp_made_up_entirely").is_none());
+    }
+
+    #[test]
+    fn checks_ranges() {
+        let schema = schema();
+        assert!(schema.check("maxplayers", "1").is_ok());
+        assert!(schema.check("maxplayers", "64").is_ok());
+        assert!(schema.check("maxplayers", "0").is_err());
+        assert!(schema.check("maxplayers", "65").is_err());
+        assert!(schema.check("MaxPlayers", "65").is_err());
+        assert!(schema.check("maxplayers", "ten").is_err());
+        assert!(schema.check("maxplayers", "10.5").is_err());
+        assert!(schema.check("hostport", "65535").is_ok());
+        assert!(schema.check("hostport", "65536").is_err());
+    }
+
+    #[test]
+    fn checks_enumerations_and_bools() {
+        let schema = schema();
+        assert!(schema.check("game_type", "6").is_ok());
+        assert!(schema.check("game_type", "4").is_err());
+        assert!(schema.check("game_mode", "1").is_ok());
+        assert!(schema.check("game_mode", "5").is_err());
+        assert!(schema.check("sv_lan", "1").is_ok());
+        assert!(schema.check("sv_lan", "true").is_err());
+        assert!(schema.check("hostname", "anything goes").is_ok());
+    }
+
+    #[test]
+    fn float_cvars_reject_non_finite_values() {
+        let spec = CvarSpec {
+            kind: CvarType::Float,
+            min: Some(0.0),
+            max: Some(1.0),
+            values: vec![],
+            description: String::new(),
+            protected: false,
+        };
+        assert!(spec.check("0.5").is_ok());
+        assert!(spec.check("1.5").is_err());
+        assert!(spec.check("NaN").is_err());
+        assert!(spec.check("inf").is_err());
+    }
+}
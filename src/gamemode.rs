@@ -0,0 +1,133 @@
+use clap::ValueEnum;
+use serde::Serialize;
+use std::fmt;
+
+/// Game mode presets, each mapping to a game_type/game_mode pair with
+/// sensible defaults for the player limit and map group.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum GameMode {
+    /// 5v5 matchmaking rules
+    Competitive,
+    /// 10v10 with relaxed rules
+    Casual,
+    /// 2v2 on small maps
+    Wingman,
+    /// Free for all with instant respawns
+    Deathmatch,
+    /// Gun game progression
+    Armsrace,
+    /// Defenders retake a planted bomb site
+    Retakes,
+    /// Custom game type for community modes
+    Custom,
+}
+
+pub struct Preset {
+    pub game_type: u32,
+    pub game_mode: u32,
+    pub maxplayers: u32,
+    pub mapgroup: &'static str,
+}
+
+impl GameMode {
+    pub fn preset(self) -> Preset {
+        let (game_type, game_mode, maxplayers, mapgroup) = match self {
+            GameMode::Competitive => (0, 1, 10, "mg_active"),
+            GameMode::Casual => (0, 0, 20, "mg_casualsigma"),
+            GameMode::Wingman => (0, 2, 4, "mg_wingman"),
+            GameMode::Deathmatch => (1, 2, 16, "mg_deathmatch"),
+            GameMode::Armsrace => (1, 0, 10, "mg_armsrace"),
+            // Retakes runs on competitive rules; the map group tells it apart
+            GameMode::Retakes => (0, 1, 9, "mg_retakes"),
+            GameMode::Custom => (3, 0, 10, "mg_custom"),
+        };
+        Preset {
+            game_type,
+            game_mode,
+            maxplayers,
+            mapgroup,
+        }
+    }
+
+    /// Finds the preset matching the game_type/game_mode pair of a config.
+    pub fn detect(game_type: &str, game_mode: &str, mapgroup: Option<&str>) -> Option<Self> {
+        let game_type: u32 = game_type.trim().parse().ok()?;
+        let game_mode: u32 = game_mode.trim().parse().ok()?;
+
+        let matches = |mode: &GameMode| {
+            let preset = mode.preset();
+            preset.game_type == game_type && preset.game_mode == game_mode
+        };
+        let candidates: Vec<GameMode> = GameMode::value_variants()
+            .iter()
+            .copied()
+            .filter(matches)
+            .collect();
+
+        candidates
+            .iter()
+            .copied()
+            .find(|mode| Some(mode.preset().mapgroup) == mapgroup)
+            .or_else(|| candidates.into_iter().find(|mode| *mode != GameMode::Retakes))
+    }
+}
+
+impl fmt::Display for GameMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let value = self.to_possible_value().expect("no skipped variants");
+        f.write_str(value.get_name())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detects_every_preset() {
+        for &mode in GameMode::value_variants() {
+            let preset = mode.preset();
+            let detected = GameMode::detect(
+                &preset.game_type.to_string(),
+                &preset.game_mode.to_string(),
+                Some(preset.mapgroup),
+            );
+            assert_eq!(detected, Some(mode));
+        }
+    }
+
+    #[test]
+    fn map_group_tells_retakes_apart() {
+        assert_eq!(
+            GameMode::detect("0", "1", Some("mg_retakes")),
+            Some(GameMode::Retakes)
+        );
+        assert_eq!(
+            GameMode::detect("0", "1", Some("mg_de_mirage")),
+            Some(GameMode::Competitive)
+        );
+        assert_eq!(
+            GameMode::detect("0", "1", None),
+            Some(GameMode::Competitive)
+        );
+        assert_eq!(
+            GameMode::detect(" 1 ", "2", None),
+            Some(GameMode::Deathmatch)
+        );
+    }
+
+    #[test]
+    fn unknown_pairs_are_not_detected() {
+        assert_eq!(GameMode::detect("0", "9", None), None);
+        assert_eq!(GameMode::detect("", "0", None), None);
+        assert_eq!(GameMode::detect("competitive", "1", None), None);
+        assert_eq!(GameMode::detect("-1", "0", None), None);
+    }
+
+    #[test]
+    fn displays_the_cli_name() {
+        assert_eq!(GameMode::Armsrace.to_string(), "armsrace");
+        assert_eq!(GameMode::Competitive.to_string(), "competitive");
+    }
+}
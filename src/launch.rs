@@ -0,0 +1,284 @@
+use anyhow::{Context, Result};
+use std::path::{Path, PathBuf};
+
+use crate::config::{InstanceConfig, ServerConfig};
+use crate::runtime::DEFAULT_PORT;
+
+const DEFAULT_MAP: &str = "de_dust2";
+const TOKEN_MASK: &str = "********";
+/// Where the game looks for the file `+exec server.cfg` names, and which it
+/// runs again after the game mode configs on every map load
+const GAME_CFG: &str = "game/csgo/cfg/server.cfg";
+/// Settings of server.cfg passed on the command line instead. `map` in the
+/// game's server.cfg would change the map on every map load.
+const LAUNCH_ONLY: &[&str] = &["map", "maxplayers"];
+
+/// The exact process a server instance is launched as.
+#[derive(Debug, Clone)]
+pub struct LaunchCommand {
+    pub executable: PathBuf,
+    pub working_dir: PathBuf,
+    pub args: Vec<String>,
+    /// Variables added to the inherited environment
+    pub env: Vec<(String, String)>,
+    token: Option<String>,
+    /// The instance's server.cfg, if it has one
+    server_config: Option<ServerConfig>,
+}
+
+impl LaunchCommand {
+    /// Builds the command line from the instance metadata and its server.cfg.
+    /// Metadata wins over server.cfg for the port and bind address. Paths are
+    /// made absolute, since the server runs in the instance directory.
+    pub fn build(server_path: &Path, instance: &InstanceConfig, executable: PathBuf) -> Result<Self> {
+        let server_path = server_path
+            .canonicalize()
+            .with_context(|| format!("Failed to resolve server path: {:?}", server_path))?;
+        let executable = executable
+            .canonicalize()
+            .with_context(|| format!("Failed to resolve server executable: {:?}", executable))?;
+        let cfg_path = server_path.join("server.cfg");
+        let loaded = if cfg_path.exists() {
+            Some(ServerConfig::load(&cfg_path)?)
+        } else {
+            None
+        };
+        let server_config = loaded.clone().unwrap_or_default();
+        let cvar = |key: &str| server_config.get(key).filter(|value| !value.is_empty());
+
+        let port = match instance.port {
+            Some(port) => port,
+            None => match cvar("hostport") {
+                Some(port) => port
+                    .parse()
+                    .with_context(|| format!("Invalid hostport value: {}", port))?,
+                None => DEFAULT_PORT,
+            },
+        };
+
+        // -usercon enables the RCON listener on the game port
+        let mut args = vec![
+            "-dedicated".to_string(),
+            "-usercon".to_string(),
+            "-port".to_string(),
+            port.to_string(),
+        ];
+        if let Some(ip) = instance.bind_ip.as_deref().or(cvar("ip")) {
+            args.extend(["-ip".to_string(), ip.to_string()]);
+        }
+        if let Some(maxplayers) = cvar("maxplayers") {
+            args.extend(["-maxplayers".to_string(), maxplayers.to_string()]);
+        }
+        args.extend(instance.launch_options.iter().cloned());
+
+        args.extend([
+            "+game_type".to_string(),
+            cvar("game_type").unwrap_or("0").to_string(),
+            "+game_mode".to_string(),
+            cvar("game_mode").unwrap_or("0").to_string(),
+        ]);
+        if let Some(mapgroup) = cvar("mapgroup") {
+            args.extend(["+mapgroup".to_string(), mapgroup.to_string()]);
+        }
+        if let Some(token) = &instance.gslt {
+            args.extend(["+sv_setsteamaccount".to_string(), token.clone()]);
+        }
+        args.extend([
+            "+exec".to_string(),
+            "server.cfg".to_string(),
+            "+map".to_string(),
+            cvar("map").unwrap_or(DEFAULT_MAP).to_string(),
+        ]);
+
+        let mut env = vec![];
+        if cfg!(target_os = "linux") {
+            // The server loads its shared libraries from next to the executable
+            if let Some(bin_dir) = executable.parent() {
+                let mut library_path = bin_dir.display().to_string();
+                if let Ok(existing) = std::env::var("LD_LIBRARY_PATH") {
+                    library_path = format!("{}:{}", library_path, existing);
+                }
+                env.push(("LD_LIBRARY_PATH".to_string(), library_path));
+            }
+        }
+
+        Ok(Self {
+            executable,
+            working_dir: server_path,
+            args,
+            env,
+            token: instance.gslt.clone(),
+            server_config: loaded,
+        })
+    }
+
+    /// Writes the instance's server.cfg where the game reads it, without the
+    /// settings that are passed on the command line. Does nothing for an
+    /// instance without a server.cfg, which keeps the game's own.
+    pub fn install_config(&self) -> Result<()> {
+        let Some(server_config) = &self.server_config else {
+            return Ok(());
+        };
+        let mut game_config = server_config.clone();
+        for key in LAUNCH_ONLY {
+            game_config.unset(key);
+        }
+
+        let path = self.working_dir.join(GAME_CFG);
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create directory: {:?}", parent))?;
+        }
+        // Replace rather than write through a hard link into a shared base
+        if path.symlink_metadata().is_ok() {
+            std::fs::remove_file(&path).with_context(|| format!("Failed to replace {:?}", path))?;
+        }
+        game_config.save(&path)
+    }
+
+    /// Arguments with the login token masked, safe to print or persist.
+    pub fn display_args(&self) -> Vec<String> {
+        self.args
+            .iter()
+            .map(|arg| match &self.token {
+                Some(token) if arg == token => TOKEN_MASK.to_string(),
+                _ => arg.clone(),
+            })
+            .collect()
+    }
+
+    /// Shell-like rendering of the command line, with the token masked.
+    pub fn command_line(&self) -> String {
+        std::iter::once(self.executable.display().to_string())
+            .chain(self.display_args())
+            .map(|arg| shell_quote(&arg))
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    pub fn to_command(&self) -> std::process::Command {
+        let mut command = std::process::Command::new(&self.executable);
+        command
+            .current_dir(&self.working_dir)
+            .args(&self.args)
+            .envs(self.env.iter().map(|(key, value)| (key, value)));
+        command
+    }
+}
+
+fn shell_quote(arg: &str) -> String {
+    let safe = !arg.is_empty()
+        && arg
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_+./:=,@".contains(c));
+    if safe {
+        arg.to_string()
+    } else {
+        format!("'{}'", arg.replace('\'', "'\\''"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TOKEN: &str = "0123456789ABCDEF0123456789ABCDEF";
+
+    fn instance(dir: &Path) -> (InstanceConfig, PathBuf) {
+        let executable = dir.join("cs2");
+        std::fs::write(&executable, "").unwrap();
+        (InstanceConfig::new(dir.to_path_buf()), executable)
+    }
+
+    #[test]
+    fn argument_order() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(
+            dir.path().join("server.cfg"),
+            "hostport 27016\nmaxplayers 10\ngame_type 0\ngame_mode 1\nmapgroup mg_active\nmap de_inferno\n",
+        )
+        .unwrap();
+        let (mut instance, executable) = instance(dir.path());
+        instance.bind_ip = Some("10.0.0.2".to_string());
+        instance.launch_options = vec!["-tickrate".to_string(), "128".to_string()];
+        instance.gslt = Some(TOKEN.to_string());
+
+        let launch = LaunchCommand::build(dir.path(), &instance, executable).unwrap();
+        assert_eq!(
+            launch.args.join(" "),
+            format!(
+                "-dedicated -usercon -port 27016 -ip 10.0.0.2 -maxplayers 10 -tickrate 128 \
+                 +game_type 0 +game_mode 1 +mapgroup mg_active +sv_setsteamaccount {} \
+                 +exec server.cfg +map de_inferno",
+                TOKEN
+            )
+        );
+        assert!(launch.executable.is_absolute());
+        assert!(launch.working_dir.is_absolute());
+    }
+
+    #[test]
+    fn metadata_wins_and_defaults_apply() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(
+            dir.path().join("server.cfg"),
+            "hostport 27016\nip 10.0.0.2\n",
+        )
+        .unwrap();
+        let (mut instance, executable) = instance(dir.path());
+        instance.port = Some(27020);
+        instance.bind_ip = Some("10.0.0.3".to_string());
+
+        let launch = LaunchCommand::build(dir.path(), &instance, executable).unwrap();
+        assert_eq!(
+            launch.args.join(" "),
+            "-dedicated -usercon -port 27020 -ip 10.0.0.3 +game_type 0 +game_mode 0 \
+             +exec server.cfg +map de_dust2"
+        );
+    }
+
+    #[test]
+    fn token_is_masked() {
+        let dir = tempfile::tempdir().unwrap();
+        let (mut instance, executable) = instance(dir.path());
+        instance.gslt = Some(TOKEN.to_string());
+
+        let launch = LaunchCommand::build(dir.path(), &instance, executable).unwrap();
+        assert!(launch.args.iter().any(|arg| arg == TOKEN));
+        let display_args = launch.display_args();
+        let index = display_args
+            .iter()
+            .position(|arg| arg == "+sv_setsteamaccount")
+            .unwrap();
+        assert_eq!(display_args[index + 1], TOKEN_MASK);
+        assert!(!display_args.iter().any(|arg| arg == TOKEN));
+        assert!(!launch.command_line().contains(TOKEN));
+    }
+
+    #[test]
+    fn quotes_for_the_shell() {
+        assert_eq!(shell_quote("+map"), "+map");
+        assert_eq!(shell_quote("/srv/cs2/bin/cs2"), "/srv/cs2/bin/cs2");
+        assert_eq!(shell_quote(""), "''");
+        assert_eq!(shell_quote("My Server"), "'My Server'");
+        assert_eq!(shell_quote("say \"hi\""), "'say \"hi\"'");
+        assert_eq!(shell_quote("it's"), "'it'\\''s'");
+        assert_eq!(shell_quote("$HOME"), "'$HOME'");
+    }
+
+    #[test]
+    fn game_config_leaves_out_launch_settings() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(
+            dir.path().join("server.cfg"),
+            "hostname \"Test\"\nmap de_nuke\nmaxplayers 10\nsv_cheats 0\n",
+        )
+        .unwrap();
+        let (instance, executable) = instance(dir.path());
+
+        let launch = LaunchCommand::build(dir.path(), &instance, executable).unwrap();
+        launch.install_config().unwrap();
+        let installed = std::fs::read_to_string(dir.path().join(GAME_CFG)).unwrap();
+        assert_eq!(installed, "hostname \"Test\"\nsv_cheats 0\n");
+    }
+}
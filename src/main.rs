@@ -1,18 +1,35 @@
 use clap::{Parser, Subcommand};
 use std::path::PathBuf;
 
-use env_logger;
-
+mod cfgfile;
 mod cli;
 mod config;
+mod console;
+mod cvars;
+mod gamemode;
+mod launch;
+mod manifest;
+mod output;
+mod overlay;
+mod profile;
+mod progress;
+mod query;
+mod rcon;
+mod runtime;
 mod server;
 mod steam;
+mod steamcmd;
+mod vdf;
 
 #[derive(Parser)]
 #[command(name = "cs2-server-cli")]
 #[command(about = "CLI tool for Counter-Strike 2 server management")]
 #[command(version)]
 struct Cli {
+    /// Output format
+    #[arg(short, long, global = true, value_enum, default_value_t = output::OutputFormat::Text)]
+    output: output::OutputFormat,
+
     #[command(subcommand)]
     command: Commands,
 }
@@ -27,16 +44,44 @@ enum Commands {
         /// Installation directory
         #[arg(short, long)]
         dir: Option<PathBuf>,
+        /// Game mode preset for the new server
+        #[arg(short, long, value_enum)]
+        mode: Option<gamemode::GameMode>,
+        /// Steam account to log in with (default: $STEAM_USERNAME, else anonymous)
+        #[arg(long)]
+        steam_user: Option<String>,
+        /// Steam beta branch to install, remembered for later updates
+        #[arg(long)]
+        branch: Option<String>,
+        /// Password of a private beta branch
+        #[arg(long, requires = "branch")]
+        branch_password: Option<String>,
+        /// Download a full copy of the game files instead of linking them
+        /// from the shared base install
+        #[arg(long)]
+        standalone: bool,
     },
     /// Start a server instance
     Start {
         /// Server instance name
         name: String,
+        /// Run attached to the terminal instead of detaching into the background
+        #[arg(short, long)]
+        foreground: bool,
+        /// Print the command line and environment without starting the server
+        #[arg(long)]
+        dry_run: bool,
     },
     /// Stop a server instance
     Stop {
         /// Server instance name
         name: String,
+        /// Announce the shutdown in-game and wait this many seconds first
+        #[arg(short, long)]
+        countdown: Option<u64>,
+        /// Seconds to wait for the server to exit before escalating to SIGTERM/SIGKILL
+        #[arg(short, long, default_value_t = 30)]
+        timeout: u64,
     },
     /// Get server status
     Status {
@@ -47,21 +92,50 @@ enum Commands {
     Update {
         /// Server instance name
         name: String,
+        /// Steam account to log in with (default: $STEAM_USERNAME, else anonymous)
+        #[arg(long)]
+        steam_user: Option<String>,
+        /// Switch to a Steam beta branch, `public` to leave the beta
+        #[arg(long)]
+        branch: Option<String>,
+        /// Password of a private beta branch
+        #[arg(long)]
+        branch_password: Option<String>,
+        /// Only check whether a newer build is available
+        #[arg(long)]
+        check: bool,
+        /// Also check every installed file and restore modified ones
+        #[arg(long, conflicts_with = "check")]
+        validate: bool,
     },
-    /// Configure server settings
-    Config {
+    /// Check the server files against Steam and restore modified or missing ones
+    Verify {
         /// Server instance name
         name: String,
-        /// Configuration key
-        key: String,
-        /// Configuration value
-        value: String,
+        /// Steam account to log in with (default: $STEAM_USERNAME, else anonymous)
+        #[arg(long)]
+        steam_user: Option<String>,
+    },
+    /// Configure server settings
+    Config {
+        #[command(subcommand)]
+        config_command: ConfigCommands,
+    },
+    /// Show or edit instance metadata
+    Instance {
+        #[command(subcommand)]
+        instance_command: InstanceCommands,
+    },
+    /// Manage the game server login token (GSLT)
+    Token {
+        #[command(subcommand)]
+        token_command: TokenCommands,
     },
     /// Install custom maps
     InstallMap {
         /// Server instance name
         name: String,
-        /// Map URL or path
+        /// Map URL, path, or workshop:<item id> to download it from the Steam Workshop
         map: String,
     },
     /// Manage plugins
@@ -78,6 +152,11 @@ enum Commands {
         /// Backup name
         backup_name: String,
     },
+    /// List configuration backups
+    Backups {
+        /// Server instance name
+        name: String,
+    },
     /// Restore server configuration
     Restore {
         /// Server instance name
@@ -87,6 +166,156 @@ enum Commands {
     },
     /// Install SteamCMD
     InstallSteamcmd,
+    /// Run a console command on a running server over RCON
+    Rcon {
+        /// Server instance name
+        name: String,
+        /// Command to execute
+        #[arg(required = true, trailing_var_arg = true, allow_hyphen_values = true)]
+        command: Vec<String>,
+    },
+    /// Open an interactive RCON console to a running server
+    Console {
+        /// Server instance name
+        name: String,
+    },
+    /// Query any CS2 server over the Steam server query protocol
+    Query {
+        /// Server address (host or host:port)
+        address: String,
+        /// Also show server rules
+        #[arg(short, long)]
+        rules: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum ConfigCommands {
+    /// Set a cvar in server.cfg
+    Set {
+        /// Server instance name
+        name: String,
+        /// Cvar name
+        key: String,
+        /// Cvar value
+        value: String,
+        /// Write the value even if it fails validation
+        #[arg(short, long)]
+        force: bool,
+        /// Where to keep the value; `instance` also survives `config render`
+        #[arg(long, value_enum, default_value_t = profile::ConfigLayer::Cfg)]
+        layer: profile::ConfigLayer,
+    },
+    /// Remove a cvar from server.cfg
+    Unset {
+        /// Server instance name
+        name: String,
+        /// Cvar name
+        key: String,
+        /// Where to remove the value from; with `instance`, a value from
+        /// defaults or profiles applies again
+        #[arg(long, value_enum, default_value_t = profile::ConfigLayer::Cfg)]
+        layer: profile::ConfigLayer,
+    },
+    /// Show the cvars set in server.cfg
+    Show {
+        /// Server instance name
+        name: String,
+        /// Show the values resolved from defaults, profiles and instance overrides
+        #[arg(long)]
+        resolved: bool,
+    },
+    /// Write defaults, profiles and instance overrides into server.cfg
+    Render {
+        /// Server instance name
+        name: String,
+        /// Write values even if they fail validation
+        #[arg(short, long)]
+        force: bool,
+    },
+    /// Show or set the profiles applied to an instance
+    Profiles {
+        /// Server instance name
+        name: String,
+        /// Profiles to apply in order, later ones win
+        profiles: Vec<String>,
+        /// Stop applying any profile
+        #[arg(long, conflicts_with = "profiles")]
+        clear: bool,
+    },
+    /// Switch to a game mode preset
+    Mode {
+        /// Server instance name
+        name: String,
+        /// Game mode preset
+        #[arg(value_enum)]
+        mode: gamemode::GameMode,
+    },
+    /// Check server.cfg for syntax errors and invalid values
+    Validate {
+        /// Server instance name
+        name: String,
+    },
+}
+
+#[derive(Subcommand)]
+enum InstanceCommands {
+    /// Show the metadata recorded for an instance
+    Show {
+        /// Server instance name
+        name: String,
+    },
+    /// Set a metadata field, an empty value clears it
+    Set {
+        /// Server instance name
+        name: String,
+        /// Field name: port, bind_ip, launch_options, tags, branch, branch_password, owner or autostart
+        field: String,
+        /// New value; tags are comma separated
+        #[arg(allow_hyphen_values = true)]
+        value: String,
+    },
+    /// Hold an instance at a build; updates install the pinned depot manifests
+    Pin {
+        /// Server instance name
+        name: String,
+        /// Depot manifest to install, e.g. 2347771=8271549381826470000; defaults
+        /// to the currently installed depots
+        #[arg(long = "manifest", value_name = "DEPOT=MANIFEST")]
+        manifests: Vec<String>,
+        /// Build ID the manifests belong to, for reporting
+        #[arg(long)]
+        build: Option<String>,
+    },
+    /// Let an instance follow its branch again
+    Unpin {
+        /// Server instance name
+        name: String,
+    },
+}
+
+#[derive(Subcommand)]
+enum TokenCommands {
+    /// Store the login token of an instance
+    Set {
+        /// Server instance name
+        name: String,
+        /// Token from steamcommunity.com/dev/managegameservers, prompted for if omitted
+        token: Option<String>,
+    },
+    /// Show whether a login token is set
+    Show {
+        /// Server instance name
+        name: String,
+        /// Print the full token instead of a masked one
+        #[arg(long)]
+        reveal: bool,
+    },
+    /// Remove the login token of an instance
+    Clear {
+        /// Server instance name
+        name: String,
+    },
 }
 
 #[derive(Subcommand)]
@@ -119,60 +348,181 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     env_logger::init();
 
     let cli = Cli::parse();
+    let output = cli.output;
 
     match cli.command {
-        Commands::Install { name, dir } => {
-            cli::install_server(&name, dir.as_deref()).await?;
+        Commands::Install {
+            name,
+            dir,
+            mode,
+            steam_user,
+            branch,
+            branch_password,
+            standalone,
+        } => {
+            let options = cli::DownloadOptions {
+                steam_user,
+                branch,
+                branch_password,
+            };
+            cli::install_server(&name, dir.as_deref(), mode, &options, standalone, output)
+                .await?;
         }
-        Commands::Start { name } => {
-            cli::start_server(&name).await?;
+        Commands::Start {
+            name,
+            foreground,
+            dry_run,
+        } => {
+            cli::start_server(&name, foreground, dry_run, output).await?;
         }
-        Commands::Stop { name } => {
-            cli::stop_server(&name).await?;
+        Commands::Stop {
+            name,
+            countdown,
+            timeout,
+        } => {
+            let options = server::StopOptions {
+                countdown,
+                timeout: std::time::Duration::from_secs(timeout),
+            };
+            cli::stop_server(&name, &options, output).await?;
         }
         Commands::Status { name } => {
-            cli::server_status(name.as_deref()).await?;
+            cli::server_status(name.as_deref(), output).await?;
         }
-        Commands::Update { name } => {
-            cli::update_server(&name).await?;
+        Commands::Update {
+            name,
+            steam_user,
+            branch,
+            branch_password,
+            check,
+            validate,
+        } => {
+            if check {
+                cli::check_update(&name, output).await?;
+            } else {
+                let options = cli::DownloadOptions {
+                    steam_user,
+                    branch,
+                    branch_password,
+                };
+                cli::update_server(&name, &options, validate, output).await?;
+            }
         }
-        Commands::Config { name, key, value } => {
-            cli::configure_server(&name, &key, &value).await?;
+        Commands::Verify { name, steam_user } => {
+            cli::verify_server(&name, steam_user.as_deref(), output).await?;
         }
+        Commands::Config { config_command } => match config_command {
+            ConfigCommands::Set {
+                name,
+                key,
+                value,
+                force,
+                layer,
+            } => {
+                cli::configure_server(&name, &key, &value, force, layer, output).await?;
+            }
+            ConfigCommands::Unset { name, key, layer } => {
+                cli::unset_config(&name, &key, layer, output).await?;
+            }
+            ConfigCommands::Show { name, resolved } => {
+                if resolved {
+                    cli::show_resolved_config(&name, output).await?;
+                } else {
+                    cli::show_config(&name, output).await?;
+                }
+            }
+            ConfigCommands::Render { name, force } => {
+                cli::render_config(&name, force, output).await?;
+            }
+            ConfigCommands::Profiles {
+                name,
+                profiles,
+                clear,
+            } => {
+                cli::set_profiles(&name, &profiles, clear, output).await?;
+            }
+            ConfigCommands::Mode { name, mode } => {
+                cli::set_game_mode(&name, mode, output).await?;
+            }
+            ConfigCommands::Validate { name } => {
+                cli::validate_config(&name, output).await?;
+            }
+        },
+        Commands::Instance { instance_command } => match instance_command {
+            InstanceCommands::Show { name } => {
+                cli::show_instance(&name, output).await?;
+            }
+            InstanceCommands::Set { name, field, value } => {
+                cli::set_instance_field(&name, &field, &value, output).await?;
+            }
+            InstanceCommands::Pin {
+                name,
+                manifests,
+                build,
+            } => {
+                cli::pin_instance(&name, &manifests, build.as_deref(), output).await?;
+            }
+            InstanceCommands::Unpin { name } => {
+                cli::unpin_instance(&name, output).await?;
+            }
+        },
+        Commands::Token { token_command } => match token_command {
+            TokenCommands::Set { name, token } => {
+                cli::set_token(&name, token.as_deref(), output).await?;
+            }
+            TokenCommands::Show { name, reveal } => {
+                cli::show_token(&name, reveal, output).await?;
+            }
+            TokenCommands::Clear { name } => {
+                cli::clear_token(&name, output).await?;
+            }
+        },
         Commands::InstallMap { name, map } => {
-            cli::install_map(&name, &map).await?;
+            cli::install_map(&name, &map, output).await?;
         }
         Commands::Plugin { plugin_command } => match plugin_command {
             PluginCommands::Install {
                 server_name,
                 plugin,
             } => {
-                cli::install_plugin(&server_name, &plugin).await?;
+                cli::install_plugin(&server_name, &plugin, output).await?;
             }
             PluginCommands::List { server_name } => {
-                cli::list_plugins(&server_name).await?;
+                cli::list_plugins(&server_name, output).await?;
             }
             PluginCommands::Remove {
                 server_name,
                 plugin,
             } => {
-                cli::remove_plugin(&server_name, &plugin).await?;
+                cli::remove_plugin(&server_name, &plugin, output).await?;
             }
             PluginCommands::Recommended => {
-                cli::show_recommended_plugins().await?;
+                cli::show_recommended_plugins(output).await?;
             }
         },
         Commands::List => {
-            cli::list_servers().await?;
+            cli::list_servers(output).await?;
         }
         Commands::Backup { name, backup_name } => {
-            cli::backup_server(&name, &backup_name).await?;
+            cli::backup_server(&name, &backup_name, output).await?;
+        }
+        Commands::Backups { name } => {
+            cli::list_backups(&name, output).await?;
         }
         Commands::Restore { name, backup_name } => {
-            cli::restore_server(&name, &backup_name).await?;
+            cli::restore_server(&name, &backup_name, output).await?;
         }
         Commands::InstallSteamcmd => {
-            cli::install_steamcmd().await?;
+            cli::install_steamcmd(output).await?;
+        }
+        Commands::Rcon { name, command } => {
+            cli::rcon_command(&name, &command.join(" "), output).await?;
+        }
+        Commands::Console { name } => {
+            cli::open_console(&name).await?;
+        }
+        Commands::Query { address, rules } => {
+            cli::query_server(&address, rules, output).await?;
         }
     }
 
@@ -0,0 +1,231 @@
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use crate::config::Config;
+use crate::steamcmd::{CS2_APP_ID, PUBLIC_BRANCH};
+use crate::vdf::{Document, Entry};
+
+/// The installed state of the server files, from steamapps/appmanifest_730.acf.
+#[derive(Debug, Clone, Serialize)]
+pub struct AppManifest {
+    pub build_id: String,
+    /// Beta branch the files were installed from, if not the public one
+    pub branch: Option<String>,
+    /// Installed manifest ID per depot ID
+    pub depots: BTreeMap<String, String>,
+}
+
+impl AppManifest {
+    pub fn path(server_path: &Path) -> PathBuf {
+        server_path
+            .join("steamapps")
+            .join(format!("appmanifest_{}.acf", CS2_APP_ID))
+    }
+
+    /// Returns None when the server files were never installed.
+    pub fn load(server_path: &Path) -> Result<Option<Self>> {
+        let path = Self::path(server_path);
+        if !path.exists() {
+            return Ok(None);
+        }
+        let content = fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read app manifest: {:?}", path))?;
+        let document = Document::parse(&content)
+            .with_context(|| format!("Failed to parse app manifest: {:?}", path))?;
+
+        let build_id = document
+            .get_path(&["AppState", "buildid"])
+            .and_then(Entry::value)
+            .with_context(|| format!("No buildid in app manifest: {:?}", path))?
+            .to_string();
+        let branch = document
+            .get_path(&["AppState", "UserConfig", "BetaKey"])
+            .and_then(Entry::value)
+            .filter(|branch| !branch.is_empty() && *branch != PUBLIC_BRANCH)
+            .map(str::to_string);
+        let depots = document
+            .get_path(&["AppState", "InstalledDepots"])
+            .and_then(Entry::children)
+            .unwrap_or_default()
+            .iter()
+            .filter_map(|depot| {
+                let manifest = depot.get("manifest").and_then(Entry::value)?;
+                Some((depot.key().to_string(), manifest.to_string()))
+            })
+            .collect();
+        Ok(Some(Self {
+            build_id,
+            branch,
+            depots,
+        }))
+    }
+
+    /// Records depots installed outside of `app_update` in the manifest, so
+    /// that the installed build is reported correctly.
+    pub fn record_depots(
+        server_path: &Path,
+        build_id: Option<&str>,
+        depots: &BTreeMap<String, String>,
+    ) -> Result<()> {
+        let path = Self::path(server_path);
+        let content = fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read app manifest: {:?}", path))?;
+        let mut document = Document::parse(&content)
+            .with_context(|| format!("Failed to parse app manifest: {:?}", path))?;
+        let state = document
+            .get_path_mut(&["AppState"])
+            .with_context(|| format!("No AppState in app manifest: {:?}", path))?;
+
+        if let Some(build_id) = build_id {
+            state.set("buildid", build_id);
+        }
+        if state.get("InstalledDepots").is_none() {
+            state.insert(Entry::block("InstalledDepots"));
+        }
+        let installed = state.get_mut("InstalledDepots").expect("just inserted");
+        for (depot, manifest) in depots {
+            if installed.get(depot).is_none() {
+                installed.insert(Entry::block(depot));
+            }
+            installed
+                .get_mut(depot)
+                .expect("just inserted")
+                .set("manifest", manifest);
+        }
+
+        fs::write(&path, document.render())
+            .with_context(|| format!("Failed to write app manifest: {:?}", path))
+    }
+}
+
+/// Size and modification time of every game file of an install. SteamCMD
+/// does not say which files a validation repaired, so they are found by
+/// comparing the snapshots from before and after.
+pub struct FileSnapshot {
+    files: BTreeMap<PathBuf, (u64, Option<SystemTime>)>,
+}
+
+impl FileSnapshot {
+    pub fn capture(server_path: &Path) -> Result<Self> {
+        let mut snapshot = Self {
+            files: BTreeMap::new(),
+        };
+        snapshot.scan(server_path, Path::new(""))?;
+        Ok(snapshot)
+    }
+
+    fn scan(&mut self, root: &Path, relative: &Path) -> Result<()> {
+        let dir = root.join(relative);
+        for entry in
+            fs::read_dir(&dir).with_context(|| format!("Failed to read directory: {:?}", dir))?
+        {
+            let entry = entry?;
+            let path = relative.join(entry.file_name());
+            // steamapps holds SteamCMD's own state, which changes on every run
+            if path == Path::new("steamapps") {
+                continue;
+            }
+            let metadata = entry.metadata()?;
+            if metadata.is_dir() {
+                self.scan(root, &path)?;
+            } else if metadata.is_file() {
+                self.files
+                    .insert(path, (metadata.len(), metadata.modified().ok()));
+            }
+        }
+        Ok(())
+    }
+
+    /// Files that were added or rewritten since `before`, relative to the
+    /// server path.
+    pub fn changed_since(&self, before: &FileSnapshot) -> Vec<PathBuf> {
+        self.files
+            .iter()
+            .filter(|(path, state)| before.files.get(*path) != Some(state))
+            .map(|(path, _)| path.clone())
+            .collect()
+    }
+}
+
+/// Finds the build ID of a branch in the output of `app_info_print`.
+pub fn latest_build_id(app_info_output: &[String], branch: &str) -> Result<String> {
+    let header = format!("\"{}\"", CS2_APP_ID);
+    let start = app_info_output
+        .iter()
+        .position(|line| line.trim() == header)
+        .context("SteamCMD did not print the app info")?;
+    // The block ends with the first unindented closing brace
+    let end = app_info_output[start..]
+        .iter()
+        .position(|line| line.trim_end() == "}")
+        .map_or(app_info_output.len(), |end| start + end + 1);
+    let document = Document::parse(&app_info_output[start..end].join("\n"))
+        .context("Failed to parse the app info printed by SteamCMD")?;
+
+    let app_id = CS2_APP_ID.to_string();
+    document
+        .get_path(&[&app_id, "depots", "branches", branch, "buildid"])
+        .and_then(Entry::value)
+        .map(str::to_string)
+        .with_context(|| format!("Branch '{}' not found in the app info", branch))
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CachedBuild {
+    pub build_id: String,
+    pub checked_at: DateTime<Utc>,
+}
+
+/// Latest build IDs per branch, so that `status` does not have to ask Steam
+/// every time.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct BuildCache {
+    #[serde(default)]
+    pub branches: BTreeMap<String, CachedBuild>,
+    /// When asking Steam last failed, per branch
+    #[serde(default)]
+    pub failed: BTreeMap<String, DateTime<Utc>>,
+}
+
+impl BuildCache {
+    fn path() -> PathBuf {
+        Config::config_dir().join("builds.json")
+    }
+
+    pub fn load() -> Self {
+        fs::read_to_string(Self::path())
+            .ok()
+            .and_then(|content| serde_json::from_str(&content).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self) -> Result<()> {
+        let path = Self::path();
+        let content =
+            serde_json::to_string_pretty(self).context("Failed to serialize build cache")?;
+        fs::create_dir_all(Config::config_dir())
+            .with_context(|| format!("Failed to create config directory: {:?}", path.parent()))?;
+        fs::write(&path, content)
+            .with_context(|| format!("Failed to write build cache: {:?}", path))
+    }
+
+    pub fn record(&mut self, branch: &str, build_id: &str) {
+        self.branches.insert(
+            branch.to_string(),
+            CachedBuild {
+                build_id: build_id.to_string(),
+                checked_at: Utc::now(),
+            },
+        );
+        self.failed.remove(branch);
+    }
+
+    pub fn record_failure(&mut self, branch: &str) {
+        self.failed.insert(branch.to_string(), Utc::now());
+    }
+}
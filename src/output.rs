@@ -0,0 +1,56 @@
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+use serde::Serialize;
+
+/// Version of the machine-readable document layout. Bump when fields are
+/// removed or change meaning; adding fields is backwards compatible.
+pub const SCHEMA_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum OutputFormat {
+    /// Human readable text
+    #[default]
+    Text,
+    /// JSON document
+    Json,
+    /// YAML document
+    Yaml,
+}
+
+#[derive(Serialize)]
+struct Document<'a, T: Serialize> {
+    schema_version: u32,
+    kind: &'a str,
+    data: &'a T,
+}
+
+impl OutputFormat {
+    pub fn is_machine(self) -> bool {
+        self != OutputFormat::Text
+    }
+
+    /// Prints `data` wrapped in a versioned document of the given kind.
+    /// Does nothing in text mode, where callers print their own output.
+    pub fn emit<T: Serialize>(self, kind: &str, data: &T) -> Result<()> {
+        let document = Document {
+            schema_version: SCHEMA_VERSION,
+            kind,
+            data,
+        };
+
+        match self {
+            OutputFormat::Text => {}
+            OutputFormat::Json => {
+                let json = serde_json::to_string_pretty(&document)
+                    .context("Failed to serialize output as JSON")?;
+                println!("{}", json);
+            }
+            OutputFormat::Yaml => {
+                let yaml = serde_yaml::to_string(&document)
+                    .context("Failed to serialize output as YAML")?;
+                print!("{}", yaml);
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,278 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use crate::manifest::AppManifest;
+
+/// Paths of an overlay whose files belong to the instance. Base files there
+/// are copied instead of linked, so that editing them cannot change the base.
+/// Maps are linked like other game files, they make up most of the install.
+const PRIVATE_PATHS: &[&str] = &[
+    "game/csgo/cfg",
+    "game/csgo/addons",
+    "game/csgo/logs",
+    "game/csgo/gameinfo.gi",
+];
+/// What the last sync linked, copied and kept, stored in the instance directory
+const STATE_FILE: &str = "overlay.json";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+struct FileStamp {
+    len: u64,
+    modified: Option<SystemTime>,
+}
+
+impl FileStamp {
+    fn of(path: &Path) -> Option<Self> {
+        let metadata = path.symlink_metadata().ok()?;
+        Some(Self {
+            len: metadata.len(),
+            modified: metadata.modified().ok(),
+        })
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct CopiedFile {
+    /// The base file at the time it was copied
+    base: FileStamp,
+    /// The copy as written; once it differs, the instance has edited it
+    copy: FileStamp,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct OverlayState {
+    /// Links into the base, as they were when linked
+    #[serde(default)]
+    linked: BTreeMap<PathBuf, FileStamp>,
+    #[serde(default)]
+    copied: BTreeMap<PathBuf, CopiedFile>,
+    /// Game files the instance replaced with its own, which are kept
+    #[serde(default)]
+    replaced: BTreeSet<PathBuf>,
+}
+
+impl OverlayState {
+    fn load(instance: &Path) -> Self {
+        fs::read_to_string(instance.join(STATE_FILE))
+            .ok()
+            .and_then(|content| serde_json::from_str(&content).ok())
+            .unwrap_or_default()
+    }
+
+    fn save(&self, instance: &Path) -> Result<()> {
+        let path = instance.join(STATE_FILE);
+        let content =
+            serde_json::to_string_pretty(self).context("Failed to serialize overlay state")?;
+        fs::write(&path, content)
+            .with_context(|| format!("Failed to write overlay state: {:?}", path))
+    }
+}
+
+/// Brings an overlay instance up to date with its shared base install. Game
+/// files are hard links into the base, so instances share the disk space and
+/// pick up a base update once linked again. Private files are copies, which
+/// follow the base until the instance edits them. Files the instance added
+/// or replaced are left alone. Returns the number of files linked, copied or
+/// removed.
+pub fn sync(base: &Path, instance: &Path) -> Result<usize> {
+    run(base, instance, false)
+}
+
+/// Links every game file of an overlay to the base again, including the ones
+/// it replaced, e.g. once the depot files of a pinned build are no longer
+/// wanted. Returns the number of files linked, copied or removed.
+pub fn restore(base: &Path, instance: &Path) -> Result<usize> {
+    run(base, instance, true)
+}
+
+/// Whether an overlay in `instance` can hard link files from `base`, which
+/// needs both on the same filesystem. Paths that do not exist yet are judged
+/// by their nearest existing parent.
+#[cfg(unix)]
+pub fn same_filesystem(base: &Path, instance: &Path) -> bool {
+    use std::os::unix::fs::MetadataExt;
+
+    let device = |path: &Path| {
+        path.ancestors()
+            .find_map(|ancestor| ancestor.metadata().ok())
+            .map(|metadata| metadata.dev())
+    };
+    matches!((device(base), device(instance)), (Some(a), Some(b)) if a == b)
+}
+
+#[cfg(not(unix))]
+pub fn same_filesystem(_base: &Path, _instance: &Path) -> bool {
+    true
+}
+
+fn run(base: &Path, instance: &Path, restore: bool) -> Result<usize> {
+    let mut sync = Sync {
+        base,
+        instance,
+        restore,
+        previous: OverlayState::load(instance),
+        state: OverlayState::default(),
+        changed: 0,
+    };
+    sync.dir(Path::new(""))?;
+    sync.remove_stale()?;
+
+    // Pinned updates write the app manifest, so every overlay has a copy
+    let manifest = AppManifest::path(base);
+    let target = AppManifest::path(instance);
+    if manifest.exists() && fs::read(&manifest).ok() != fs::read(&target).ok() {
+        fs::create_dir_all(target.parent().unwrap_or(instance))
+            .with_context(|| format!("Failed to create directory: {:?}", target.parent()))?;
+        fs::copy(&manifest, &target)
+            .with_context(|| format!("Failed to copy {:?} to {:?}", manifest, target))?;
+        sync.changed += 1;
+    }
+
+    sync.state.save(instance)?;
+    Ok(sync.changed)
+}
+
+fn is_private(path: &Path) -> bool {
+    PRIVATE_PATHS
+        .iter()
+        .any(|private| path.starts_with(private))
+}
+
+struct Sync<'a> {
+    base: &'a Path,
+    instance: &'a Path,
+    /// Also link the files the instance replaced
+    restore: bool,
+    previous: OverlayState,
+    state: OverlayState,
+    changed: usize,
+}
+
+impl Sync<'_> {
+    fn dir(&mut self, relative: &Path) -> Result<()> {
+        let dir = self.base.join(relative);
+        for entry in
+            fs::read_dir(&dir).with_context(|| format!("Failed to read directory: {:?}", dir))?
+        {
+            let entry = entry?;
+            let path = relative.join(entry.file_name());
+            // SteamCMD's own state and downloads stay with the base
+            if path == Path::new("steamapps") {
+                continue;
+            }
+            let source = entry.path();
+            let target = self.instance.join(&path);
+
+            if entry.file_type()?.is_dir() {
+                fs::create_dir_all(&target)
+                    .with_context(|| format!("Failed to create directory: {:?}", target))?;
+                self.dir(&path)?;
+            } else if is_private(&path) {
+                self.copy(path, &source, &target)?;
+            } else {
+                self.link(path, &source, &target)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn link(&mut self, path: PathBuf, source: &Path, target: &Path) -> Result<()> {
+        if !same_file(source, target) {
+            if let Some(current) = FileStamp::of(target) {
+                // Anything but the link of the last sync was put there by the
+                // instance
+                if !self.restore && self.previous.linked.get(&path) != Some(&current) {
+                    self.state.replaced.insert(path);
+                    return Ok(());
+                }
+                remove_existing(target)?;
+            }
+            fs::hard_link(source, target).with_context(|| {
+                format!(
+                    "Failed to link {:?} to {:?}; the shared base and the instance must be on the same filesystem",
+                    target, source
+                )
+            })?;
+            self.changed += 1;
+        }
+        let stamp =
+            FileStamp::of(target).with_context(|| format!("Failed to read {:?}", target))?;
+        self.state.linked.insert(path, stamp);
+        Ok(())
+    }
+
+    fn copy(&mut self, path: PathBuf, source: &Path, target: &Path) -> Result<()> {
+        let base = FileStamp::of(source).with_context(|| format!("Failed to read {:?}", source))?;
+        let copied = self.previous.copied.get(&path);
+        let refresh = match FileStamp::of(target) {
+            None => true,
+            // Still a hard link from before the path was private
+            Some(_) if same_file(source, target) => true,
+            // Untouched since the last sync, so it follows the base
+            Some(current) if copied.is_some_and(|copied| copied.copy == current) => {
+                copied.is_some_and(|copied| copied.base != base)
+            }
+            // Edited or added by the instance
+            _ => return Ok(()),
+        };
+
+        if refresh {
+            remove_existing(target)?;
+            fs::copy(source, target)
+                .with_context(|| format!("Failed to copy {:?} to {:?}", source, target))?;
+            self.changed += 1;
+            let copy =
+                FileStamp::of(target).with_context(|| format!("Failed to read {:?}", target))?;
+            self.state.copied.insert(path, CopiedFile { base, copy });
+        } else if let Some(copied) = copied {
+            self.state.copied.insert(path, copied.clone());
+        }
+        Ok(())
+    }
+
+    /// Drops links to files the base no longer has. Copies and replaced
+    /// files are kept, since the instance may still depend on them.
+    fn remove_stale(&mut self) -> Result<()> {
+        for (path, stamp) in &self.previous.linked {
+            // Paths that turned private are copies now, not stale links
+            if self.state.linked.contains_key(path)
+                || self.base.join(path).symlink_metadata().is_ok()
+            {
+                continue;
+            }
+            let target = self.instance.join(path);
+            if FileStamp::of(&target).as_ref() == Some(stamp) {
+                fs::remove_file(&target)
+                    .with_context(|| format!("Failed to remove {:?}", target))?;
+                self.changed += 1;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn remove_existing(path: &Path) -> Result<()> {
+    if path.symlink_metadata().is_ok() {
+        fs::remove_file(path).with_context(|| format!("Failed to replace {:?}", path))?;
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+fn same_file(a: &Path, b: &Path) -> bool {
+    use std::os::unix::fs::MetadataExt;
+
+    match (a.symlink_metadata(), b.symlink_metadata()) {
+        (Ok(a), Ok(b)) => a.dev() == b.dev() && a.ino() == b.ino(),
+        _ => false,
+    }
+}
+
+#[cfg(not(unix))]
+fn same_file(_a: &Path, _b: &Path) -> bool {
+    false
+}
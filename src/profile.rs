@@ -0,0 +1,312 @@
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::config::Config;
+
+pub const INSTANCE_FILE: &str = "instance.toml";
+
+/// Cvars contributed by one configuration layer.
+#[derive(Debug, Default, Deserialize)]
+struct Layer {
+    #[serde(default)]
+    cvars: BTreeMap<String, toml::Value>,
+}
+
+/// Per-instance layer, stored next to server.cfg.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct InstanceOverrides {
+    /// Profiles applied in order, later ones win
+    #[serde(default)]
+    pub profiles: Vec<String>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub cvars: BTreeMap<String, toml::Value>,
+}
+
+/// Where `config set` and `config unset` write a cvar.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum ConfigLayer {
+    /// server.cfg only; `config render` replaces values a layer also sets
+    #[default]
+    Cfg,
+    /// instance.toml, which wins over defaults and profiles, and server.cfg
+    Instance,
+}
+
+/// Where a resolved value comes from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Source {
+    Defaults,
+    Profile(String),
+    Instance,
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Source::Defaults => write!(f, "defaults"),
+            Source::Profile(name) => write!(f, "profile:{}", name),
+            Source::Instance => write!(f, "instance"),
+        }
+    }
+}
+
+impl Serialize for Source {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ResolvedValue {
+    pub value: String,
+    pub source: Source,
+    /// Lower layers whose value was overridden, lowest first
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub overrides: Vec<Source>,
+}
+
+pub fn profile_path(name: &str) -> PathBuf {
+    profile_path_in(&Config::config_dir(), name)
+}
+
+fn defaults_path_in(config_dir: &Path) -> PathBuf {
+    config_dir.join("defaults.toml")
+}
+
+fn profile_path_in(config_dir: &Path, name: &str) -> PathBuf {
+    config_dir.join("profiles").join(format!("{}.toml", name))
+}
+
+impl InstanceOverrides {
+    pub fn load(server_path: &Path) -> Result<Self> {
+        let path = server_path.join(INSTANCE_FILE);
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let content = fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read instance overrides: {:?}", path))?;
+        toml::from_str(&content)
+            .with_context(|| format!("Failed to parse instance overrides: {:?}", path))
+    }
+
+    /// Sets a cvar override. Keys are stored lowercase, as the engine
+    /// ignores their case.
+    pub fn set_cvar(&mut self, key: &str, value: &str) {
+        self.unset_cvar(key);
+        // Whole numbers are stored as TOML integers when that keeps the value
+        let value = match value.parse::<i64>() {
+            Ok(number) if number.to_string() == value => toml::Value::Integer(number),
+            _ => toml::Value::String(value.to_string()),
+        };
+        self.cvars.insert(key.to_ascii_lowercase(), value);
+    }
+
+    /// Removes a cvar override. Returns false if there was none.
+    pub fn unset_cvar(&mut self, key: &str) -> bool {
+        let before = self.cvars.len();
+        self.cvars
+            .retain(|existing, _| !existing.eq_ignore_ascii_case(key));
+        self.cvars.len() != before
+    }
+
+    pub fn save(&self, server_path: &Path) -> Result<()> {
+        let path = server_path.join(INSTANCE_FILE);
+        let content = toml::to_string(self).context("Failed to serialize instance overrides")?;
+        fs::write(&path, content)
+            .with_context(|| format!("Failed to write instance overrides: {:?}", path))?;
+        Ok(())
+    }
+}
+
+/// Resolves the cvars of an instance from global defaults, its profiles and
+/// its own overrides, in that order. Keys are lowercased, so layers override
+/// each other regardless of case.
+pub fn resolve(server_path: &Path) -> Result<BTreeMap<String, ResolvedValue>> {
+    resolve_in(&Config::config_dir(), server_path)
+}
+
+fn resolve_in(config_dir: &Path, server_path: &Path) -> Result<BTreeMap<String, ResolvedValue>> {
+    let overrides = InstanceOverrides::load(server_path)?;
+
+    let defaults = load_layer(&defaults_path_in(config_dir), false)?;
+    let mut layers = vec![(Source::Defaults, defaults)];
+    for name in &overrides.profiles {
+        let layer = load_layer(&profile_path_in(config_dir, name), true)
+            .with_context(|| format!("Failed to load profile '{}'", name))?;
+        layers.push((Source::Profile(name.clone()), layer));
+    }
+    layers.push((
+        Source::Instance,
+        Layer {
+            cvars: overrides.cvars,
+        },
+    ));
+
+    let mut resolved: BTreeMap<String, ResolvedValue> = BTreeMap::new();
+    for (source, layer) in layers {
+        for (key, value) in layer.cvars {
+            let key = key.to_ascii_lowercase();
+            let value = cvar_value(&value)
+                .with_context(|| format!("Invalid value for '{}' in {}", key, source))?;
+            match resolved.get_mut(&key) {
+                Some(existing) => {
+                    let previous = std::mem::replace(&mut existing.source, source.clone());
+                    existing.overrides.push(previous);
+                    existing.value = value;
+                }
+                None => {
+                    resolved.insert(
+                        key,
+                        ResolvedValue {
+                            value,
+                            source: source.clone(),
+                            overrides: vec![],
+                        },
+                    );
+                }
+            }
+        }
+    }
+    Ok(resolved)
+}
+
+fn load_layer(path: &Path, required: bool) -> Result<Layer> {
+    if !path.exists() {
+        if required {
+            anyhow::bail!("Profile file {:?} does not exist", path);
+        }
+        return Ok(Layer::default());
+    }
+    let content = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read {:?}", path))?;
+    toml::from_str(&content).with_context(|| format!("Failed to parse {:?}", path))
+}
+
+/// Converts a TOML value to its cfg form; booleans become 1 and 0.
+fn cvar_value(value: &toml::Value) -> Result<String> {
+    match value {
+        toml::Value::String(s) => Ok(s.clone()),
+        toml::Value::Integer(i) => Ok(i.to_string()),
+        toml::Value::Float(f) => Ok(f.to_string()),
+        toml::Value::Boolean(b) => Ok(if *b { "1" } else { "0" }.to_string()),
+        _ => anyhow::bail!("expected a string, number or boolean"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write(path: &Path, content: &str) {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, content).unwrap();
+    }
+
+    #[test]
+    fn later_layers_win() {
+        let config_dir = tempfile::tempdir().unwrap();
+        let server_dir = tempfile::tempdir().unwrap();
+        write(
+            &defaults_path_in(config_dir.path()),
+            "[cvars]\nhostname = \"Default\"\nsv_cheats = false\nmp_maxrounds = 24\nsv_lan = 0\n",
+        );
+        write(
+            &profile_path_in(config_dir.path(), "practice"),
+            "[cvars]\nsv_cheats = true\nmp_maxrounds = 30\nmp_warmuptime = 60\n",
+        );
+        write(
+            &profile_path_in(config_dir.path(), "short"),
+            "[cvars]\nMP_MAXROUNDS = 16\n",
+        );
+        write(
+            &server_dir.path().join(INSTANCE_FILE),
+            "profiles = [\"practice\", \"short\"]\n\n[cvars]\nhostname = \"Mine\"\nmp_warmuptime = 1.5\n",
+        );
+
+        let resolved = resolve_in(config_dir.path(), server_dir.path()).unwrap();
+        let entry = |key: &str| {
+            let entry = &resolved[key];
+            (
+                entry.value.as_str(),
+                entry.source.to_string(),
+                entry.overrides.clone(),
+            )
+        };
+        let practice = Source::Profile("practice".to_string());
+
+        assert_eq!(
+            entry("hostname"),
+            ("Mine", "instance".to_string(), vec![Source::Defaults])
+        );
+        assert_eq!(
+            entry("sv_cheats"),
+            ("1", "profile:practice".to_string(), vec![Source::Defaults])
+        );
+        assert_eq!(
+            entry("mp_maxrounds"),
+            (
+                "16",
+                "profile:short".to_string(),
+                vec![Source::Defaults, practice.clone()]
+            )
+        );
+        assert_eq!(
+            entry("mp_warmuptime"),
+            ("1.5", "instance".to_string(), vec![practice])
+        );
+        assert_eq!(entry("sv_lan"), ("0", "defaults".to_string(), vec![]));
+    }
+
+    #[test]
+    fn works_without_any_layer() {
+        let config_dir = tempfile::tempdir().unwrap();
+        let server_dir = tempfile::tempdir().unwrap();
+        assert!(resolve_in(config_dir.path(), server_dir.path())
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn missing_profiles_and_bad_values_fail() {
+        let config_dir = tempfile::tempdir().unwrap();
+        let server_dir = tempfile::tempdir().unwrap();
+        let mut overrides = InstanceOverrides {
+            profiles: vec!["missing".to_string()],
+            ..Default::default()
+        };
+        overrides.save(server_dir.path()).unwrap();
+        let error = resolve_in(config_dir.path(), server_dir.path()).unwrap_err();
+        assert!(format!("{:#}", error).contains("profile 'missing'"));
+
+        overrides.profiles.clear();
+        overrides.save(server_dir.path()).unwrap();
+        write(
+            &defaults_path_in(config_dir.path()),
+            "[cvars]\nmp_maxrounds = [24]\n",
+        );
+        assert!(resolve_in(config_dir.path(), server_dir.path()).is_err());
+    }
+
+    #[test]
+    fn instance_cvars_ignore_case() {
+        let mut overrides = InstanceOverrides::default();
+        overrides.set_cvar("MP_MaxRounds", "24");
+        overrides.set_cvar("hostname", "007");
+        overrides.set_cvar("mp_maxrounds", "30");
+        assert_eq!(overrides.cvars.len(), 2);
+        assert_eq!(overrides.cvars["mp_maxrounds"], toml::Value::Integer(30));
+        // Leading zeros would be lost as an integer
+        assert_eq!(
+            overrides.cvars["hostname"],
+            toml::Value::String("007".to_string())
+        );
+        assert!(overrides.unset_cvar("MP_MAXROUNDS"));
+        assert!(!overrides.unset_cvar("mp_maxrounds"));
+    }
+}
@@ -0,0 +1,179 @@
+use serde::Serialize;
+use std::io::{self, IsTerminal, Write};
+use std::time::Instant;
+
+use crate::output::OutputFormat;
+use crate::steamcmd::{parse_progress, ProgressLine};
+
+const BAR_WIDTH: usize = 30;
+/// Weight of the newest sample in the smoothed download rate
+const RATE_SMOOTHING: f64 = 0.3;
+
+/// Events written to stderr, one JSON object per line, in machine-readable mode.
+#[derive(Serialize)]
+#[serde(tag = "event", rename_all = "lowercase")]
+enum Event<'a> {
+    Progress {
+        state: &'a str,
+        state_code: String,
+        percent: f64,
+        bytes_done: u64,
+        bytes_total: u64,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        rate_bytes_per_sec: Option<u64>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        eta_secs: Option<u64>,
+    },
+    Output {
+        line: &'a str,
+    },
+}
+
+/// Turns SteamCMD output into a progress bar on a terminal, or into JSON
+/// events in machine-readable mode. Other output is passed through.
+pub struct ProgressReporter {
+    output: OutputFormat,
+    interactive: bool,
+    bar_visible: bool,
+    last: Option<(Instant, ProgressLine)>,
+    rate: Option<f64>,
+}
+
+impl ProgressReporter {
+    pub fn new(output: OutputFormat) -> Self {
+        Self {
+            output,
+            interactive: !output.is_machine() && io::stdout().is_terminal(),
+            bar_visible: false,
+            last: None,
+            rate: None,
+        }
+    }
+
+    pub fn line(&mut self, line: &str) {
+        match parse_progress(line) {
+            Some(progress) => self.progress(line, progress),
+            None => self.passthrough(line, true),
+        }
+    }
+
+    /// Output that is not newline terminated yet, like a Steam Guard prompt.
+    pub fn partial(&mut self, text: &str) {
+        self.passthrough(text, false);
+    }
+
+    pub fn finish(&mut self) {
+        if self.bar_visible {
+            println!();
+            self.bar_visible = false;
+        }
+    }
+
+    fn progress(&mut self, line: &str, progress: ProgressLine) {
+        let now = Instant::now();
+        match &self.last {
+            Some((at, last)) if last.state_code == progress.state_code => {
+                let elapsed = now.duration_since(*at).as_secs_f64();
+                if elapsed > 0.0 && progress.bytes_done >= last.bytes_done {
+                    let sample = (progress.bytes_done - last.bytes_done) as f64 / elapsed;
+                    self.rate = Some(match self.rate {
+                        Some(rate) => rate + RATE_SMOOTHING * (sample - rate),
+                        None => sample,
+                    });
+                }
+            }
+            // A new state (e.g. downloading -> verifying) has its own rate
+            _ => self.rate = None,
+        }
+        let remaining = progress.bytes_total.saturating_sub(progress.bytes_done);
+        let eta = self
+            .rate
+            .filter(|rate| *rate > 0.0)
+            .map(|rate| (remaining as f64 / rate) as u64);
+
+        if self.output.is_machine() {
+            self.emit(&Event::Progress {
+                state: &progress.state,
+                state_code: format!("0x{:x}", progress.state_code),
+                percent: progress.percent,
+                bytes_done: progress.bytes_done,
+                bytes_total: progress.bytes_total,
+                rate_bytes_per_sec: self.rate.map(|rate| rate as u64),
+                eta_secs: eta,
+            });
+        } else if self.interactive {
+            self.draw_bar(&progress, eta);
+        } else {
+            println!("{}", line);
+        }
+        self.last = Some((now, progress));
+    }
+
+    fn draw_bar(&mut self, progress: &ProgressLine, eta: Option<u64>) {
+        let filled = ((progress.percent / 100.0) * BAR_WIDTH as f64).round() as usize;
+        let filled = filled.min(BAR_WIDTH);
+        let mut text = format!(
+            "{:<16} [{}{}] {:5.1}% {} / {}",
+            progress.state,
+            "#".repeat(filled),
+            "-".repeat(BAR_WIDTH - filled),
+            progress.percent,
+            format_bytes(progress.bytes_done),
+            format_bytes(progress.bytes_total),
+        );
+        if let Some(rate) = self.rate {
+            text.push_str(&format!("  {}/s", format_bytes(rate as u64)));
+        }
+        if let Some(eta) = eta {
+            text.push_str(&format!("  ETA {}", format_duration(eta)));
+        }
+        let mut stdout = io::stdout();
+        let _ = write!(stdout, "\r\x1b[2K{}", text);
+        let _ = stdout.flush();
+        self.bar_visible = true;
+    }
+
+    fn passthrough(&mut self, text: &str, newline: bool) {
+        if self.output.is_machine() {
+            // Partial output is forwarded as a line of its own
+            if !text.trim().is_empty() {
+                self.emit(&Event::Output { line: text });
+            }
+            return;
+        }
+        if self.bar_visible {
+            print!("\r\x1b[2K");
+            self.bar_visible = false;
+        }
+        if newline {
+            println!("{}", text);
+        } else {
+            print!("{}", text);
+            let _ = io::stdout().flush();
+        }
+    }
+
+    fn emit(&self, event: &Event) {
+        if let Ok(json) = serde_json::to_string(event) {
+            eprintln!("{}", json);
+        }
+    }
+}
+
+pub(crate) fn format_bytes(bytes: u64) -> String {
+    const MIB: f64 = 1024.0 * 1024.0;
+    if bytes as f64 >= 1024.0 * MIB {
+        format!("{:.1} GiB", bytes as f64 / (1024.0 * MIB))
+    } else {
+        format!("{:.0} MiB", bytes as f64 / MIB)
+    }
+}
+
+pub(crate) fn format_duration(seconds: u64) -> String {
+    let (hours, minutes, seconds) = (seconds / 3600, seconds / 60 % 60, seconds % 60);
+    if hours > 0 {
+        format!("{}h{:02}m{:02}s", hours, minutes, seconds)
+    } else {
+        format!("{}m{:02}s", minutes, seconds)
+    }
+}
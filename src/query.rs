@@ -0,0 +1,537 @@
+use anyhow::{Context, Result};
+use log::debug;
+use serde::Serialize;
+use std::time::Duration;
+use tokio::net::UdpSocket;
+use tokio::time::timeout;
+
+// Packet headers of the Steam server query (A2S) protocol
+const SINGLE_PACKET: i32 = -1;
+const SPLIT_PACKET: i32 = -2;
+const A2S_INFO: u8 = 0x54;
+const A2S_PLAYER: u8 = 0x55;
+const A2S_RULES: u8 = 0x56;
+const S2A_INFO: u8 = 0x49;
+const S2A_PLAYER: u8 = 0x44;
+const S2A_RULES: u8 = 0x45;
+const S2C_CHALLENGE: u8 = 0x41;
+
+const MAX_PACKET_SIZE: usize = 1400;
+// Upper bound on the number of fragments we are willing to reassemble
+const MAX_SPLIT_PACKETS: u8 = 64;
+// Servers may answer a challenged request with a new challenge once more
+const MAX_CHALLENGE_ROUNDS: usize = 3;
+
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// Response to A2S_INFO.
+#[derive(Debug, Clone, Serialize)]
+pub struct ServerInfo {
+    pub protocol: u8,
+    pub name: String,
+    pub map: String,
+    pub folder: String,
+    pub game: String,
+    pub app_id: u16,
+    pub players: u8,
+    pub max_players: u8,
+    pub bots: u8,
+    pub server_type: char,
+    pub environment: char,
+    pub password: bool,
+    pub vac: bool,
+    pub version: String,
+    pub port: Option<u16>,
+    pub steam_id: Option<u64>,
+    pub keywords: Option<String>,
+    pub game_id: Option<u64>,
+}
+
+/// One entry of the A2S_PLAYER response.
+#[derive(Debug, Clone, Serialize)]
+pub struct PlayerInfo {
+    pub name: String,
+    pub score: i32,
+    /// Seconds connected
+    pub duration: f32,
+}
+
+pub struct QueryClient {
+    socket: UdpSocket,
+    timeout: Duration,
+}
+
+impl QueryClient {
+    pub async fn connect(address: &str, io_timeout: Duration) -> Result<Self> {
+        let socket = UdpSocket::bind("0.0.0.0:0")
+            .await
+            .context("Failed to bind UDP socket")?;
+        socket
+            .connect(address)
+            .await
+            .with_context(|| format!("Failed to resolve query address {}", address))?;
+        Ok(Self {
+            socket,
+            timeout: io_timeout,
+        })
+    }
+
+    pub async fn info(&self) -> Result<ServerInfo> {
+        let mut request = vec![A2S_INFO];
+        request.extend_from_slice(b"Source Engine Query\0");
+
+        let payload = self.request(&request, S2A_INFO, false).await?;
+        parse_info(&payload)
+    }
+
+    pub async fn players(&self) -> Result<Vec<PlayerInfo>> {
+        let payload = self.request(&[A2S_PLAYER], S2A_PLAYER, true).await?;
+        parse_players(&payload)
+    }
+
+    pub async fn rules(&self) -> Result<Vec<(String, String)>> {
+        let payload = self.request(&[A2S_RULES], S2A_RULES, true).await?;
+        parse_rules(&payload)
+    }
+
+    /// Sends a request, answering challenges until the expected response arrives.
+    /// Player and rules requests always carry a challenge, starting with -1.
+    async fn request(&self, request: &[u8], expected: u8, challenged: bool) -> Result<Vec<u8>> {
+        let mut challenge: Option<[u8; 4]> = if challenged { Some([0xFF; 4]) } else { None };
+
+        for _ in 0..MAX_CHALLENGE_ROUNDS {
+            let mut packet = SINGLE_PACKET.to_le_bytes().to_vec();
+            packet.extend_from_slice(request);
+            if let Some(challenge) = challenge {
+                packet.extend_from_slice(&challenge);
+            }
+            self.socket
+                .send(&packet)
+                .await
+                .context("Failed to send query")?;
+
+            let response = self.receive().await?;
+            let (&kind, payload) = response.split_first().context("Empty query response")?;
+            match kind {
+                S2C_CHALLENGE => {
+                    let value: [u8; 4] = payload
+                        .get(..4)
+                        .context("Truncated challenge response")?
+                        .try_into()?;
+                    debug!("Received query challenge {:02x?}", value);
+                    challenge = Some(value);
+                }
+                kind if kind == expected => return Ok(payload.to_vec()),
+                kind => anyhow::bail!("Unexpected query response type 0x{:02x}", kind),
+            }
+        }
+
+        anyhow::bail!("Server kept answering with challenges")
+    }
+
+    /// Receives one logical response, reassembling split packets.
+    async fn receive(&self) -> Result<Vec<u8>> {
+        let packet = self.receive_packet().await?;
+        let mut reader = Reader::new(&packet);
+        match reader.i32()? {
+            SINGLE_PACKET => Ok(reader.rest().to_vec()),
+            SPLIT_PACKET => self.receive_split(reader).await,
+            header => anyhow::bail!("Invalid query packet header {}", header),
+        }
+    }
+
+    async fn receive_split(&self, mut first: Reader<'_>) -> Result<Vec<u8>> {
+        let (id, total, number, payload) = read_split_header(&mut first)?;
+        if id as u32 & 0x8000_0000 != 0 {
+            anyhow::bail!("Compressed split query responses are not supported");
+        }
+        if total == 0 || total > MAX_SPLIT_PACKETS {
+            anyhow::bail!("Invalid split packet count {}", total);
+        }
+
+        let mut parts: Vec<Option<Vec<u8>>> = vec![None; total as usize];
+        store_part(&mut parts, number, payload)?;
+
+        while parts.iter().any(|part| part.is_none()) {
+            let packet = self.receive_packet().await?;
+            let mut reader = Reader::new(&packet);
+            if reader.i32()? != SPLIT_PACKET {
+                continue;
+            }
+            let (part_id, _, number, payload) = read_split_header(&mut reader)?;
+            if part_id != id {
+                debug!("Ignoring fragment of unrelated response {}", part_id);
+                continue;
+            }
+            store_part(&mut parts, number, payload)?;
+        }
+
+        let combined: Vec<u8> = parts.into_iter().flatten().flatten().collect();
+        let mut reader = Reader::new(&combined);
+        if reader.i32()? != SINGLE_PACKET {
+            anyhow::bail!("Invalid header in reassembled query response");
+        }
+        Ok(reader.rest().to_vec())
+    }
+
+    async fn receive_packet(&self) -> Result<Vec<u8>> {
+        let mut buffer = vec![0u8; MAX_PACKET_SIZE];
+        let size = timeout(self.timeout, self.socket.recv(&mut buffer))
+            .await
+            .context("Timed out waiting for query response")?
+            .context("Failed to receive query response")?;
+        buffer.truncate(size);
+        Ok(buffer)
+    }
+}
+
+fn read_split_header<'a>(reader: &mut Reader<'a>) -> Result<(i32, u8, u8, &'a [u8])> {
+    let id = reader.i32()?;
+    let total = reader.u8()?;
+    let number = reader.u8()?;
+    // Source engine split packets carry the maximum packet size
+    let _size = reader.i16()?;
+    Ok((id, total, number, reader.rest()))
+}
+
+fn store_part(parts: &mut [Option<Vec<u8>>], number: u8, payload: &[u8]) -> Result<()> {
+    let slot = parts
+        .get_mut(number as usize)
+        .with_context(|| format!("Split packet number {} out of range", number))?;
+    *slot = Some(payload.to_vec());
+    Ok(())
+}
+
+fn parse_info(payload: &[u8]) -> Result<ServerInfo> {
+    let mut reader = Reader::new(payload);
+    let mut info = ServerInfo {
+        protocol: reader.u8()?,
+        name: reader.string()?,
+        map: reader.string()?,
+        folder: reader.string()?,
+        game: reader.string()?,
+        app_id: reader.i16()? as u16,
+        players: reader.u8()?,
+        max_players: reader.u8()?,
+        bots: reader.u8()?,
+        server_type: reader.u8()? as char,
+        environment: reader.u8()? as char,
+        password: reader.u8()? != 0,
+        vac: reader.u8()? != 0,
+        version: reader.string()?,
+        port: None,
+        steam_id: None,
+        keywords: None,
+        game_id: None,
+    };
+
+    // Extra data flag, absent on some older servers
+    if let Ok(edf) = reader.u8() {
+        if edf & 0x80 != 0 {
+            info.port = Some(reader.i16()? as u16);
+        }
+        if edf & 0x10 != 0 {
+            info.steam_id = Some(reader.u64()?);
+        }
+        if edf & 0x40 != 0 {
+            let _tv_port = reader.i16()?;
+            let _tv_name = reader.string()?;
+        }
+        if edf & 0x20 != 0 {
+            info.keywords = Some(reader.string()?);
+        }
+        if edf & 0x01 != 0 {
+            info.game_id = Some(reader.u64()?);
+        }
+    }
+
+    Ok(info)
+}
+
+fn parse_players(payload: &[u8]) -> Result<Vec<PlayerInfo>> {
+    let mut reader = Reader::new(payload);
+    let count = reader.u8()?;
+
+    let mut players = Vec::with_capacity(count as usize);
+    for _ in 0..count {
+        // The count is capped at 255, stop early on truncated responses
+        if reader.is_empty() {
+            break;
+        }
+        let _index = reader.u8()?;
+        players.push(PlayerInfo {
+            name: reader.string()?,
+            score: reader.i32()?,
+            duration: reader.f32()?,
+        });
+    }
+
+    Ok(players)
+}
+
+fn parse_rules(payload: &[u8]) -> Result<Vec<(String, String)>> {
+    let mut reader = Reader::new(payload);
+    let count = reader.i16()?;
+
+    let mut rules = Vec::with_capacity(count.max(0) as usize);
+    for _ in 0..count {
+        if reader.is_empty() {
+            break;
+        }
+        rules.push((reader.string()?, reader.string()?));
+    }
+
+    Ok(rules)
+}
+
+/// Little-endian cursor over a query payload.
+struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self { data }
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8]> {
+        if self.data.len() < len {
+            anyhow::bail!("Truncated query response");
+        }
+        let (head, tail) = self.data.split_at(len);
+        self.data = tail;
+        Ok(head)
+    }
+
+    fn u8(&mut self) -> Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn i16(&mut self) -> Result<i16> {
+        Ok(i16::from_le_bytes(self.take(2)?.try_into()?))
+    }
+
+    fn i32(&mut self) -> Result<i32> {
+        Ok(i32::from_le_bytes(self.take(4)?.try_into()?))
+    }
+
+    fn u64(&mut self) -> Result<u64> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into()?))
+    }
+
+    fn f32(&mut self) -> Result<f32> {
+        Ok(f32::from_le_bytes(self.take(4)?.try_into()?))
+    }
+
+    fn string(&mut self) -> Result<String> {
+        let end = self
+            .data
+            .iter()
+            .position(|&b| b == 0)
+            .context("Unterminated string in query response")?;
+        let value = String::from_utf8_lossy(&self.data[..end]).into_owned();
+        self.data = &self.data[end + 1..];
+        Ok(value)
+    }
+
+    fn rest(&mut self) -> &'a [u8] {
+        std::mem::take(&mut self.data)
+    }
+
+    fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// S2A_INFO payload of a CS2 server, without the leading header and type byte
+    const INFO: &[u8] = b"\x11My CS2 Server\0de_dust2\0csgo\0Counter-Strike 2\0\xda\x02\
+        \x05\x10\x01dl\x00\x011.10.5.7\0";
+
+    fn info_with_extra_data() -> Vec<u8> {
+        let mut payload = INFO.to_vec();
+        payload.push(0x80 | 0x40 | 0x20 | 0x10 | 0x01);
+        payload.extend_from_slice(&27015u16.to_le_bytes());
+        payload.extend_from_slice(&90264000000000001u64.to_le_bytes());
+        payload.extend_from_slice(&27020u16.to_le_bytes());
+        payload.extend_from_slice(b"SourceTV\0");
+        payload.extend_from_slice(b"secure,competitive\0");
+        payload.extend_from_slice(&730u64.to_le_bytes());
+        payload
+    }
+
+    #[test]
+    fn parses_info() {
+        let info = parse_info(INFO).unwrap();
+        assert_eq!(info.protocol, 17);
+        assert_eq!(info.name, "My CS2 Server");
+        assert_eq!(info.map, "de_dust2");
+        assert_eq!(info.folder, "csgo");
+        assert_eq!(info.game, "Counter-Strike 2");
+        assert_eq!(info.app_id, 730);
+        assert_eq!((info.players, info.max_players, info.bots), (5, 16, 1));
+        assert_eq!((info.server_type, info.environment), ('d', 'l'));
+        assert!(!info.password);
+        assert!(info.vac);
+        assert_eq!(info.version, "1.10.5.7");
+        assert_eq!(info.port, None);
+        assert_eq!(info.steam_id, None);
+        assert_eq!(info.keywords, None);
+        assert_eq!(info.game_id, None);
+    }
+
+    #[test]
+    fn parses_info_extra_data() {
+        let info = parse_info(&info_with_extra_data()).unwrap();
+        assert_eq!(info.version, "1.10.5.7");
+        assert_eq!(info.port, Some(27015));
+        assert_eq!(info.steam_id, Some(90264000000000001));
+        assert_eq!(info.keywords.as_deref(), Some("secure,competitive"));
+        assert_eq!(info.game_id, Some(730));
+    }
+
+    #[test]
+    fn rejects_truncated_info() {
+        assert!(parse_info(&INFO[..20]).is_err());
+        let extra = info_with_extra_data();
+        assert!(parse_info(&extra[..extra.len() - 3]).is_err());
+    }
+
+    #[test]
+    fn parses_players() {
+        let mut payload = vec![2];
+        payload.push(0);
+        payload.extend_from_slice(b"alice\0");
+        payload.extend_from_slice(&12i32.to_le_bytes());
+        payload.extend_from_slice(&61.5f32.to_le_bytes());
+        payload.push(0);
+        payload.extend_from_slice(b"bob\0");
+        payload.extend_from_slice(&(-1i32).to_le_bytes());
+        payload.extend_from_slice(&3.0f32.to_le_bytes());
+
+        let players = parse_players(&payload).unwrap();
+        assert_eq!(players.len(), 2);
+        assert_eq!(players[0].name, "alice");
+        assert_eq!(players[0].score, 12);
+        assert_eq!(players[0].duration, 61.5);
+        assert_eq!(players[1].name, "bob");
+        assert_eq!(players[1].score, -1);
+
+        // The count claims more players than the response holds
+        payload[0] = 5;
+        assert_eq!(parse_players(&payload).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn parses_rules() {
+        let mut payload = 2i16.to_le_bytes().to_vec();
+        payload.extend_from_slice(b"mp_maxrounds\x0024\0sv_cheats\x000\0");
+        let rules = parse_rules(&payload).unwrap();
+        assert_eq!(
+            rules,
+            [
+                ("mp_maxrounds".to_string(), "24".to_string()),
+                ("sv_cheats".to_string(), "0".to_string())
+            ]
+        );
+    }
+
+    /// A query client talking to a fake server on a local socket.
+    async fn fake_server() -> (QueryClient, UdpSocket) {
+        let server = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let address = server.local_addr().unwrap().to_string();
+        let client = QueryClient::connect(&address, DEFAULT_TIMEOUT)
+            .await
+            .unwrap();
+        (client, server)
+    }
+
+    fn single(kind: u8, payload: &[u8]) -> Vec<u8> {
+        let mut packet = SINGLE_PACKET.to_le_bytes().to_vec();
+        packet.push(kind);
+        packet.extend_from_slice(payload);
+        packet
+    }
+
+    fn split(id: i32, total: u8, number: u8, payload: &[u8]) -> Vec<u8> {
+        let mut packet = SPLIT_PACKET.to_le_bytes().to_vec();
+        packet.extend_from_slice(&id.to_le_bytes());
+        packet.extend_from_slice(&[total, number]);
+        packet.extend_from_slice(&(MAX_PACKET_SIZE as i16).to_le_bytes());
+        packet.extend_from_slice(payload);
+        packet
+    }
+
+    #[tokio::test]
+    async fn answers_challenges() {
+        let (client, server) = fake_server().await;
+        let serve = async {
+            let mut buffer = [0u8; MAX_PACKET_SIZE];
+            let (size, peer) = server.recv_from(&mut buffer).await.unwrap();
+            assert_eq!(&buffer[..size], b"\xff\xff\xff\xff\x55\xff\xff\xff\xff");
+            server
+                .send_to(&single(S2C_CHALLENGE, b"\x12\x34\x56\x78"), peer)
+                .await
+                .unwrap();
+
+            let (size, peer) = server.recv_from(&mut buffer).await.unwrap();
+            assert_eq!(&buffer[..size], b"\xff\xff\xff\xff\x55\x12\x34\x56\x78");
+            let mut players = vec![1, 0];
+            players.extend_from_slice(b"alice\0");
+            players.extend_from_slice(&7i32.to_le_bytes());
+            players.extend_from_slice(&1.0f32.to_le_bytes());
+            server
+                .send_to(&single(S2A_PLAYER, &players), peer)
+                .await
+                .unwrap();
+        };
+
+        let (players, _) = tokio::join!(client.players(), serve);
+        let players = players.unwrap();
+        assert_eq!(players.len(), 1);
+        assert_eq!(players[0].name, "alice");
+        assert_eq!(players[0].score, 7);
+    }
+
+    #[tokio::test]
+    async fn reassembles_split_responses() {
+        let (client, server) = fake_server().await;
+        let response = single(S2A_INFO, &info_with_extra_data());
+        let (first, second) = response.split_at(40);
+        let serve = async {
+            let mut buffer = [0u8; MAX_PACKET_SIZE];
+            let (_, peer) = server.recv_from(&mut buffer).await.unwrap();
+            // Out of order, with a fragment of another response in between
+            for packet in [
+                split(7, 2, 1, second),
+                split(8, 2, 0, b"unrelated"),
+                split(7, 2, 0, first),
+            ] {
+                server.send_to(&packet, peer).await.unwrap();
+            }
+        };
+
+        let (info, _) = tokio::join!(client.info(), serve);
+        let info = info.unwrap();
+        assert_eq!(info.name, "My CS2 Server");
+        assert_eq!(info.keywords.as_deref(), Some("secure,competitive"));
+        assert_eq!(info.game_id, Some(730));
+    }
+
+    #[tokio::test]
+    async fn rejects_compressed_split_responses() {
+        let (client, server) = fake_server().await;
+        let serve = async {
+            let mut buffer = [0u8; MAX_PACKET_SIZE];
+            let (_, peer) = server.recv_from(&mut buffer).await.unwrap();
+            let packet = split(0x8000_0007u32 as i32, 2, 0, b"compressed");
+            server.send_to(&packet, peer).await.unwrap();
+        };
+
+        let (info, _) = tokio::join!(client.info(), serve);
+        assert!(info.is_err());
+    }
+}
@@ -0,0 +1,363 @@
+use anyhow::{Context, Result};
+use log::{debug, warn};
+use std::path::Path;
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::time::timeout;
+
+use crate::config::ServerConfig;
+use crate::server::instance_address;
+
+// Packet types of the Source RCON protocol
+const SERVERDATA_AUTH: i32 = 3;
+const SERVERDATA_AUTH_RESPONSE: i32 = 2;
+const SERVERDATA_EXECCOMMAND: i32 = 2;
+const SERVERDATA_RESPONSE_VALUE: i32 = 0;
+
+// id + type + two null terminators
+const PACKET_HEADER_SIZE: i32 = 10;
+const MAX_PACKET_SIZE: i32 = 4096 + PACKET_HEADER_SIZE;
+
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug)]
+struct Packet {
+    id: i32,
+    kind: i32,
+    body: String,
+}
+
+/// Address and password used to reach an instance over RCON.
+#[derive(Debug, Clone)]
+pub struct RconTarget {
+    pub host: String,
+    pub port: u16,
+    pub password: String,
+}
+
+impl RconTarget {
+    /// Reads `rcon_password` from the instance's server.cfg; the address is the
+    /// instance's game address, since CS2 serves RCON on the game port.
+    pub fn for_instance(server_path: &Path) -> Result<Self> {
+        let cfg_path = server_path.join("server.cfg");
+
+        let password = ServerConfig::lookup(&cfg_path, "rcon_password")?
+            .filter(|password| !password.is_empty())
+            .with_context(|| format!("rcon_password is not set in {:?}", cfg_path))?;
+
+        let (host, port) = instance_address(server_path)?;
+
+        Ok(Self {
+            host,
+            port,
+            password,
+        })
+    }
+
+    pub fn address(&self) -> String {
+        format!("{}:{}", self.host, self.port)
+    }
+}
+
+pub struct RconClient {
+    stream: TcpStream,
+    next_id: i32,
+    timeout: Duration,
+}
+
+impl RconClient {
+    /// Connects to the server and authenticates with the given password.
+    pub async fn connect(target: &RconTarget, io_timeout: Duration) -> Result<Self> {
+        let address = target.address();
+        let stream = timeout(io_timeout, TcpStream::connect(&address))
+            .await
+            .with_context(|| format!("Timed out connecting to RCON at {}", address))?
+            .with_context(|| format!("Failed to connect to RCON at {}", address))?;
+
+        let mut client = Self {
+            stream,
+            next_id: 1,
+            timeout: io_timeout,
+        };
+        client.authenticate(&target.password).await?;
+        Ok(client)
+    }
+
+    async fn authenticate(&mut self, password: &str) -> Result<()> {
+        let id = self.allocate_id();
+        self.send(id, SERVERDATA_AUTH, password).await?;
+
+        // The server sends an empty RESPONSE_VALUE before the AUTH_RESPONSE
+        loop {
+            let packet = self.receive().await?;
+            if packet.kind != SERVERDATA_AUTH_RESPONSE {
+                continue;
+            }
+            if packet.id == -1 {
+                anyhow::bail!("RCON authentication failed: wrong password");
+            }
+            if packet.id == id {
+                debug!("RCON authenticated");
+                return Ok(());
+            }
+        }
+    }
+
+    /// Executes a command and returns its full output. A server that never
+    /// answers, as some do for commands without output, costs the full
+    /// timeout and yields whatever arrived, possibly nothing.
+    pub async fn exec(&mut self, command: &str) -> Result<String> {
+        let id = self.allocate_id();
+        self.send(id, SERVERDATA_EXECCOMMAND, command).await?;
+
+        // Responses larger than one packet are split; an empty RESPONSE_VALUE sent
+        // right after the command is mirrored back once all parts have arrived
+        let marker = self.allocate_id();
+        self.send(marker, SERVERDATA_RESPONSE_VALUE, "").await?;
+
+        let mut output = String::new();
+        loop {
+            let packet = match timeout(self.timeout, read_packet(&mut self.stream)).await {
+                Ok(Ok(packet)) => packet,
+                // A closed connection keeps the output that made it, if any
+                Ok(Err(e)) if !output.is_empty() => {
+                    warn!("RCON response did not terminate cleanly: {}", e);
+                    break;
+                }
+                Ok(Err(e)) => return Err(e),
+                // Some servers never mirror the marker, or answer commands
+                // without output with nothing at all
+                Err(_) => {
+                    warn!("No end of RCON response within {:?}", self.timeout);
+                    break;
+                }
+            };
+
+            if packet.id == marker {
+                break;
+            }
+            if packet.id == id && packet.kind == SERVERDATA_RESPONSE_VALUE {
+                output.push_str(&packet.body);
+            }
+            // Anything else is a leftover from an earlier exchange
+        }
+
+        Ok(output)
+    }
+
+    fn allocate_id(&mut self) -> i32 {
+        let id = self.next_id;
+        self.next_id = self.next_id.wrapping_add(1).max(1);
+        id
+    }
+
+    async fn send(&mut self, id: i32, kind: i32, body: &str) -> Result<()> {
+        let buffer = encode_packet(id, kind, body)?;
+        timeout(self.timeout, self.stream.write_all(&buffer))
+            .await
+            .context("Timed out sending RCON packet")?
+            .context("Failed to send RCON packet")?;
+        Ok(())
+    }
+
+    async fn receive(&mut self) -> Result<Packet> {
+        timeout(self.timeout, read_packet(&mut self.stream))
+            .await
+            .context("Timed out waiting for RCON response")?
+    }
+}
+
+/// Frames a packet: size, id and type as little-endian i32, then the body
+/// and two null terminators. The size counts everything after itself.
+fn encode_packet(id: i32, kind: i32, body: &str) -> Result<Vec<u8>> {
+    let size = body.len() as i32 + PACKET_HEADER_SIZE;
+    if size > MAX_PACKET_SIZE {
+        anyhow::bail!("RCON command is too long ({} bytes)", body.len());
+    }
+
+    let mut buffer = Vec::with_capacity(size as usize + 4);
+    buffer.extend_from_slice(&size.to_le_bytes());
+    buffer.extend_from_slice(&id.to_le_bytes());
+    buffer.extend_from_slice(&kind.to_le_bytes());
+    buffer.extend_from_slice(body.as_bytes());
+    buffer.extend_from_slice(&[0, 0]);
+    Ok(buffer)
+}
+
+async fn read_packet<R: AsyncRead + Unpin>(stream: &mut R) -> Result<Packet> {
+    let size = stream
+        .read_i32_le()
+        .await
+        .context("RCON connection closed")?;
+    if !(PACKET_HEADER_SIZE..=MAX_PACKET_SIZE).contains(&size) {
+        anyhow::bail!("Invalid RCON packet size: {}", size);
+    }
+
+    let mut buffer = vec![0u8; size as usize];
+    stream
+        .read_exact(&mut buffer)
+        .await
+        .context("RCON connection closed")?;
+
+    let id = i32::from_le_bytes(buffer[0..4].try_into()?);
+    let kind = i32::from_le_bytes(buffer[4..8].try_into()?);
+    let body = &buffer[8..];
+    let end = body.iter().position(|&b| b == 0).unwrap_or(body.len());
+    let body = String::from_utf8_lossy(&body[..end]).into_owned();
+
+    Ok(Packet { id, kind, body })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::net::TcpListener;
+
+    async fn write_packet(stream: &mut TcpStream, id: i32, kind: i32, body: &str) {
+        stream
+            .write_all(&encode_packet(id, kind, body).unwrap())
+            .await
+            .unwrap();
+    }
+
+    /// Accepts one client, answers its authentication, then hands the
+    /// connection to `serve`.
+    async fn fake_server<F, Fut>(serve: F) -> RconTarget
+    where
+        F: FnOnce(TcpStream) -> Fut + Send + 'static,
+        Fut: std::future::Future<Output = ()> + Send,
+    {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let auth = read_packet(&mut stream).await.unwrap();
+            assert_eq!(auth.kind, SERVERDATA_AUTH);
+            let id = if auth.body == "secret" { auth.id } else { -1 };
+            write_packet(&mut stream, auth.id, SERVERDATA_RESPONSE_VALUE, "").await;
+            write_packet(&mut stream, id, SERVERDATA_AUTH_RESPONSE, "").await;
+            serve(stream).await;
+        });
+        RconTarget {
+            host: "127.0.0.1".to_string(),
+            port,
+            password: "secret".to_string(),
+        }
+    }
+
+    #[test]
+    fn frames_packets() {
+        let packet = encode_packet(7, SERVERDATA_EXECCOMMAND, "status").unwrap();
+        assert_eq!(&packet[0..4], &16i32.to_le_bytes());
+        assert_eq!(&packet[4..8], &7i32.to_le_bytes());
+        assert_eq!(&packet[8..12], &SERVERDATA_EXECCOMMAND.to_le_bytes());
+        assert_eq!(&packet[12..18], b"status");
+        assert_eq!(&packet[18..], &[0, 0]);
+
+        let empty = encode_packet(8, SERVERDATA_RESPONSE_VALUE, "").unwrap();
+        assert_eq!(empty, [10, 0, 0, 0, 8, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+
+        assert!(encode_packet(1, SERVERDATA_EXECCOMMAND, &"x".repeat(4096)).is_ok());
+        assert!(encode_packet(1, SERVERDATA_EXECCOMMAND, &"x".repeat(4097)).is_err());
+    }
+
+    #[tokio::test]
+    async fn round_trips_packets() {
+        let mut bytes = encode_packet(-1, SERVERDATA_AUTH_RESPONSE, "").unwrap();
+        bytes.extend(encode_packet(42, SERVERDATA_RESPONSE_VALUE, "hostname: Test\n").unwrap());
+        let mut reader = bytes.as_slice();
+
+        let packet = read_packet(&mut reader).await.unwrap();
+        assert_eq!((packet.id, packet.kind), (-1, SERVERDATA_AUTH_RESPONSE));
+        assert_eq!(packet.body, "");
+        let packet = read_packet(&mut reader).await.unwrap();
+        assert_eq!((packet.id, packet.kind), (42, SERVERDATA_RESPONSE_VALUE));
+        assert_eq!(packet.body, "hostname: Test\n");
+        assert!(read_packet(&mut reader).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn rejects_invalid_sizes() {
+        for size in [9i32, MAX_PACKET_SIZE + 1, -1] {
+            let bytes = size.to_le_bytes();
+            assert!(read_packet(&mut bytes.as_slice()).await.is_err());
+        }
+    }
+
+    #[tokio::test]
+    async fn joins_split_responses_up_to_the_marker() {
+        let target = fake_server(|mut stream| async move {
+            let command = read_packet(&mut stream).await.unwrap();
+            assert_eq!(command.kind, SERVERDATA_EXECCOMMAND);
+            assert_eq!(command.body, "cvarlist");
+            let marker = read_packet(&mut stream).await.unwrap();
+            assert_eq!(marker.kind, SERVERDATA_RESPONSE_VALUE);
+            assert_eq!(marker.body, "");
+
+            write_packet(&mut stream, 99, SERVERDATA_RESPONSE_VALUE, "stale").await;
+            write_packet(&mut stream, command.id, SERVERDATA_RESPONSE_VALUE, "first ").await;
+            write_packet(&mut stream, command.id, SERVERDATA_RESPONSE_VALUE, "second").await;
+            write_packet(&mut stream, marker.id, SERVERDATA_RESPONSE_VALUE, "").await;
+            write_packet(&mut stream, command.id, SERVERDATA_RESPONSE_VALUE, "late").await;
+            let _ = read_packet(&mut stream).await;
+        })
+        .await;
+
+        let mut client = RconClient::connect(&target, DEFAULT_TIMEOUT).await.unwrap();
+        assert_eq!(client.exec("cvarlist").await.unwrap(), "first second");
+    }
+
+    #[tokio::test]
+    async fn returns_output_without_a_marker() {
+        let target = fake_server(|mut stream| async move {
+            let command = read_packet(&mut stream).await.unwrap();
+            let _marker = read_packet(&mut stream).await.unwrap();
+            write_packet(&mut stream, command.id, SERVERDATA_RESPONSE_VALUE, "done").await;
+            let _ = read_packet(&mut stream).await;
+        })
+        .await;
+
+        let mut client = RconClient::connect(&target, Duration::from_millis(200))
+            .await
+            .unwrap();
+        assert_eq!(client.exec("say hi").await.unwrap(), "done");
+    }
+
+    #[tokio::test]
+    async fn returns_nothing_without_any_reply() {
+        let target = fake_server(|mut stream| async move {
+            // Read everything, answer nothing
+            while read_packet(&mut stream).await.is_ok() {}
+        })
+        .await;
+
+        let mut client = RconClient::connect(&target, Duration::from_millis(200))
+            .await
+            .unwrap();
+        assert_eq!(client.exec("mp_restartgame 1").await.unwrap(), "");
+    }
+
+    #[tokio::test]
+    async fn fails_when_the_connection_closes() {
+        let target = fake_server(|mut stream| async move {
+            let _command = read_packet(&mut stream).await.unwrap();
+        })
+        .await;
+
+        let mut client = RconClient::connect(&target, DEFAULT_TIMEOUT).await.unwrap();
+        assert!(client.exec("quit").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn rejects_wrong_passwords() {
+        let mut target = fake_server(|_| async {}).await;
+        target.password = "wrong".to_string();
+
+        let error = RconClient::connect(&target, DEFAULT_TIMEOUT)
+            .await
+            .err()
+            .unwrap();
+        assert!(error.to_string().contains("wrong password"));
+    }
+}
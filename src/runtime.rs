@@ -0,0 +1,301 @@
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const STATE_FILE: &str = "runtime.json";
+
+pub const DEFAULT_PORT: u16 = 27015;
+
+/// Runtime state of a started server, persisted in the instance directory so
+/// that later CLI invocations can find the process again.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RuntimeState {
+    pub pid: u32,
+    pub started_at: DateTime<Utc>,
+    pub executable: PathBuf,
+    pub args: Vec<String>,
+    pub port: u16,
+    /// Process start time in clock ticks since boot (from /proc/<pid>/stat),
+    /// used to detect PID reuse.
+    pub start_ticks: Option<u64>,
+    /// Console log the detached server writes to.
+    #[serde(default)]
+    pub log_file: Option<PathBuf>,
+    /// Set while a stop is in progress.
+    #[serde(default)]
+    pub stopping_since: Option<DateTime<Utc>>,
+}
+
+impl RuntimeState {
+    pub fn new(pid: u32, executable: PathBuf, args: Vec<String>) -> Self {
+        let port = port_from_args(&args).unwrap_or(DEFAULT_PORT);
+        Self {
+            pid,
+            started_at: Utc::now(),
+            executable,
+            args,
+            port,
+            start_ticks: process_start_ticks(pid),
+            log_file: None,
+            stopping_since: None,
+        }
+    }
+
+    /// Address passed with `-ip` at launch, if any.
+    pub fn bind_ip(&self) -> Option<&str> {
+        self.args
+            .windows(2)
+            .find(|pair| pair[0] == "-ip")
+            .map(|pair| pair[1].as_str())
+    }
+
+    pub fn load(server_path: &Path) -> Result<Option<Self>> {
+        let path = Self::path(server_path);
+        if !path.exists() {
+            return Ok(None);
+        }
+        let content = fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read runtime state: {:?}", path))?;
+        let state = serde_json::from_str(&content)
+            .with_context(|| format!("Failed to parse runtime state: {:?}", path))?;
+        Ok(Some(state))
+    }
+
+    pub fn save(&self, server_path: &Path) -> Result<()> {
+        let path = Self::path(server_path);
+        let content =
+            serde_json::to_string_pretty(self).context("Failed to serialize runtime state")?;
+        fs::write(&path, content)
+            .with_context(|| format!("Failed to write runtime state: {:?}", path))?;
+        Ok(())
+    }
+
+    pub fn clear(server_path: &Path) -> Result<()> {
+        let path = Self::path(server_path);
+        if path.exists() {
+            fs::remove_file(&path)
+                .with_context(|| format!("Failed to remove runtime state: {:?}", path))?;
+        }
+        Ok(())
+    }
+
+    /// Returns true if the recorded PID still belongs to the process we started.
+    pub fn is_alive(&self) -> bool {
+        if !process_exists(self.pid) {
+            return false;
+        }
+
+        // A different start time means the PID has been reused
+        if let (Some(recorded), Some(current)) = (self.start_ticks, process_start_ticks(self.pid))
+        {
+            if recorded != current {
+                return false;
+            }
+        }
+
+        match process_executable(self.pid) {
+            Some(exe) => {
+                // The binary may have been replaced by an update while running
+                let exe = exe
+                    .to_str()
+                    .and_then(|s| s.strip_suffix(" (deleted)"))
+                    .map(PathBuf::from)
+                    .unwrap_or(exe);
+                let expected = fs::canonicalize(&self.executable)
+                    .unwrap_or_else(|_| self.executable.clone());
+                exe == expected
+            }
+            // Not readable (e.g. different user), trust the start time check
+            None => true,
+        }
+    }
+
+    fn path(server_path: &Path) -> PathBuf {
+        server_path.join(STATE_FILE)
+    }
+}
+
+fn port_from_args(args: &[String]) -> Option<u16> {
+    args.windows(2)
+        .find(|pair| pair[0] == "-port")
+        .and_then(|pair| pair[1].parse().ok())
+}
+
+/// Checks whether a process with the given PID exists and is not a zombie.
+#[cfg(unix)]
+pub fn process_exists(pid: u32) -> bool {
+    let proc_dir = PathBuf::from(format!("/proc/{}", pid));
+    if Path::new("/proc").exists() {
+        if !proc_dir.exists() {
+            return false;
+        }
+        return process_state(pid) != Some('Z');
+    }
+
+    // No procfs (e.g. macOS), fall back to probing with signal 0
+    unsafe { libc::kill(pid as libc::pid_t, 0) == 0 }
+}
+
+#[cfg(not(unix))]
+pub fn process_exists(_pid: u32) -> bool {
+    false
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Signal {
+    Term,
+    Kill,
+}
+
+/// Sends a signal to the process. Returns false if the process no longer
+/// exists, and an error if it could not be signalled.
+#[cfg(unix)]
+pub fn send_signal(pid: u32, signal: Signal) -> Result<bool> {
+    let signum = match signal {
+        Signal::Term => libc::SIGTERM,
+        Signal::Kill => libc::SIGKILL,
+    };
+    let result = unsafe { libc::kill(pid as libc::pid_t, signum) };
+    if result != 0 {
+        let err = std::io::Error::last_os_error();
+        if err.raw_os_error() == Some(libc::ESRCH) {
+            return Ok(false);
+        }
+        anyhow::bail!("Failed to send {:?} to process {}: {}", signal, pid, err);
+    }
+    Ok(true)
+}
+
+#[cfg(not(unix))]
+pub fn send_signal(pid: u32, _signal: Signal) -> Result<bool> {
+    anyhow::bail!("Signalling process {} is only supported on Unix", pid);
+}
+
+fn read_proc_stat(pid: u32) -> Option<Vec<String>> {
+    let stat = fs::read_to_string(format!("/proc/{}/stat", pid)).ok()?;
+    // The command name is wrapped in parentheses and may contain spaces
+    let rest = &stat[stat.rfind(')')? + 1..];
+    Some(rest.split_whitespace().map(|s| s.to_string()).collect())
+}
+
+fn process_state(pid: u32) -> Option<char> {
+    read_proc_stat(pid)?.first()?.chars().next()
+}
+
+fn process_start_ticks(pid: u32) -> Option<u64> {
+    // starttime is field 22 of /proc/<pid>/stat, i.e. index 19 after the command name
+    read_proc_stat(pid)?.get(19)?.parse().ok()
+}
+
+fn process_executable(pid: u32) -> Option<PathBuf> {
+    fs::read_link(format!("/proc/{}/exe", pid)).ok()
+}
+
+/// Resource usage of a running process, read from procfs.
+#[derive(Debug, Clone, Default)]
+pub struct ProcessStats {
+    pub rss_bytes: Option<u64>,
+    pub cpu_percent: Option<f64>,
+    /// Local UDP ports and listening TCP ports of the process
+    pub ports: Vec<u16>,
+}
+
+impl ProcessStats {
+    /// Samples the process; CPU usage is measured over `interval`.
+    pub async fn sample(pid: u32, interval: std::time::Duration) -> Self {
+        let before = process_cpu_ticks(pid);
+        tokio::time::sleep(interval).await;
+        let after = process_cpu_ticks(pid);
+
+        let cpu_percent = match (before, after, clock_ticks_per_second()) {
+            (Some(before), Some(after), Some(ticks)) if after >= before => {
+                let used = (after - before) as f64 / ticks as f64;
+                Some(used / interval.as_secs_f64() * 100.0)
+            }
+            _ => None,
+        };
+
+        Self {
+            rss_bytes: process_rss_bytes(pid),
+            cpu_percent,
+            ports: process_ports(pid),
+        }
+    }
+}
+
+fn process_cpu_ticks(pid: u32) -> Option<u64> {
+    // utime and stime are fields 14 and 15 of /proc/<pid>/stat
+    let stat = read_proc_stat(pid)?;
+    let utime: u64 = stat.get(11)?.parse().ok()?;
+    let stime: u64 = stat.get(12)?.parse().ok()?;
+    Some(utime + stime)
+}
+
+#[cfg(unix)]
+fn clock_ticks_per_second() -> Option<u64> {
+    let ticks = unsafe { libc::sysconf(libc::_SC_CLK_TCK) };
+    (ticks > 0).then_some(ticks as u64)
+}
+
+#[cfg(not(unix))]
+fn clock_ticks_per_second() -> Option<u64> {
+    None
+}
+
+fn process_rss_bytes(pid: u32) -> Option<u64> {
+    let status = fs::read_to_string(format!("/proc/{}/status", pid)).ok()?;
+    let line = status.lines().find(|line| line.starts_with("VmRSS:"))?;
+    let kilobytes: u64 = line.split_whitespace().nth(1)?.parse().ok()?;
+    Some(kilobytes * 1024)
+}
+
+fn process_ports(pid: u32) -> Vec<u16> {
+    // Socket file descriptors link to "socket:[<inode>]"
+    let inodes: Vec<String> = match fs::read_dir(format!("/proc/{}/fd", pid)) {
+        Ok(entries) => entries
+            .flatten()
+            .filter_map(|entry| fs::read_link(entry.path()).ok())
+            .filter_map(|target| {
+                let target = target.to_str()?;
+                let inode = target.strip_prefix("socket:[")?.strip_suffix(']')?;
+                Some(inode.to_string())
+            })
+            .collect(),
+        Err(_) => return vec![],
+    };
+
+    let mut ports = vec![];
+    for table in ["udp", "udp6", "tcp", "tcp6"] {
+        let content = match fs::read_to_string(format!("/proc/{}/net/{}", pid, table)) {
+            Ok(content) => content,
+            Err(_) => continue,
+        };
+        for line in content.lines().skip(1) {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            // local_address is field 1 ("ADDR:PORT" in hex), inode is field 9
+            let (Some(local), Some(inode)) = (fields.get(1), fields.get(9)) else {
+                continue;
+            };
+            if !inodes.iter().any(|i| i == inode) {
+                continue;
+            }
+            // Only count listening TCP sockets, not outgoing connections
+            if table.starts_with("tcp") && fields.get(3) != Some(&"0A") {
+                continue;
+            }
+            let port = local
+                .rsplit(':')
+                .next()
+                .and_then(|port| u16::from_str_radix(port, 16).ok());
+            if let Some(port) = port {
+                ports.push(port);
+            }
+        }
+    }
+
+    ports.sort();
+    ports.dedup();
+    ports
+}
@@ -1,92 +1,480 @@
+use crate::config::{InstanceConfig, ServerConfig};
+use crate::gamemode::GameMode;
+use crate::launch::LaunchCommand;
+use crate::manifest::AppManifest;
+use crate::profile::INSTANCE_FILE;
+use crate::query::{self, PlayerInfo, QueryClient, ServerInfo};
+use crate::rcon::{self, RconClient, RconTarget};
+use crate::runtime::{
+    process_exists, send_signal, ProcessStats, RuntimeState, Signal, DEFAULT_PORT,
+};
 use anyhow::{Context, Result};
-use std::path::PathBuf;
+use log::{info, warn};
+use serde::{Serialize, Serializer};
+use std::path::{Path, PathBuf};
 use std::process::Stdio;
+use std::time::Duration;
 use tokio::fs;
 use tokio::process::Command as TokioCommand;
+use tokio::signal;
 
-#[derive(Debug, Clone, PartialEq)]
-pub enum ServerStatus {
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ServerState {
     Stopped,
     Starting,
     Running,
     Stopping,
 }
 
+/// Health derived from the process state and whether the server answers queries.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Health {
+    /// Running and answering queries
+    Healthy,
+    /// Starting up or shutting down
+    Pending,
+    /// Process is alive but does not answer queries
+    Unresponsive,
+    /// No server process
+    Offline,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ServerStatus {
+    pub state: ServerState,
+    pub health: Health,
+    pub pid: Option<u32>,
+    #[serde(rename = "uptime_seconds", serialize_with = "serialize_seconds")]
+    pub uptime: Option<Duration>,
+    pub rss_bytes: Option<u64>,
+    pub cpu_percent: Option<f64>,
+    /// Game port the server is configured for
+    pub port: Option<u16>,
+    /// Whether the process actually has the game port bound
+    pub port_open: bool,
+    pub last_log_line: Option<String>,
+    /// Game mode preset configured in server.cfg
+    pub mode: Option<GameMode>,
+    pub info: Option<ServerInfo>,
+    /// Build ID of the installed server files
+    pub installed_build: Option<String>,
+    /// Whether Steam has a newer build for the instance's branch; unknown
+    /// until compared against the latest build
+    pub needs_update: Option<bool>,
+    /// Configuration problems worth surfacing, e.g. a missing login token
+    pub warnings: Vec<String>,
+}
+
+fn serialize_seconds<S: Serializer>(
+    duration: &Option<Duration>,
+    serializer: S,
+) -> std::result::Result<S::Ok, S::Error> {
+    duration.map(|d| d.as_secs()).serialize(serializer)
+}
+
+// Servers not answering queries this soon after launch are still loading
+const STARTUP_GRACE: Duration = Duration::from_secs(90);
+const CPU_SAMPLE_INTERVAL: Duration = Duration::from_millis(250);
+// How much of the console log is searched for login token errors
+const TOKEN_LOG_SCAN_SIZE: u64 = 256 * 1024;
+
+#[derive(Debug, Clone)]
+pub struct StopOptions {
+    /// Seconds of in-game countdown announced with `say` before stopping
+    pub countdown: Option<u64>,
+    /// How long to wait for the server to exit before escalating to the next stage
+    pub timeout: Duration,
+}
+
+impl Default for StopOptions {
+    fn default() -> Self {
+        Self {
+            countdown: None,
+            timeout: Duration::from_secs(30),
+        }
+    }
+}
+
+/// How a stop request ended.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StopOutcome {
+    /// No server process was running
+    NotRunning,
+    /// Exited cleanly after `quit` over RCON
+    Quit,
+    /// Exited after SIGTERM
+    Terminated,
+    /// Had to be killed with SIGKILL
+    Killed,
+}
+
+/// The outcome of a stop request, and what had to be left out on the way.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct StopReport {
+    pub outcome: StopOutcome,
+    /// A countdown was requested, but could not be announced without RCON
+    pub countdown_skipped: bool,
+}
+
+const KILL_TIMEOUT: Duration = Duration::from_secs(10);
+
+// Remaining seconds at which the shutdown countdown is repeated in-game
+const COUNTDOWN_ANNOUNCEMENTS: &[u64] = &[300, 120, 60, 30, 10, 5, 4, 3, 2, 1];
+
 pub struct ServerManager {
     server_path: PathBuf,
-    process: Option<tokio::process::Child>,
 }
 
+const CONSOLE_LOGS_KEPT: usize = 10;
+
 impl ServerManager {
     pub fn new(server_path: PathBuf) -> Self {
-        Self {
-            server_path,
-            process: None,
-        }
+        Self { server_path }
     }
 
-    pub async fn start(&mut self) -> Result<()> {
-        if self.process.is_some() {
-            anyhow::bail!("Server is already running");
+    /// Starts the server. When `detach` is set the process is moved into its own
+    /// session with output redirected to `logs/console-*.log` and this call returns
+    /// as soon as the server is up; otherwise it runs attached to the terminal until
+    /// it exits.
+    pub async fn start(&mut self, instance: &InstanceConfig, detach: bool) -> Result<()> {
+        if let Some(state) = self.running_state()? {
+            anyhow::bail!("Server is already running (pid {})", state.pid);
+        }
+
+        let launch = self.launch_command(instance)?;
+        launch.install_config()?;
+        info!("Launching {}", launch.command_line());
+
+        if detach {
+            self.spawn_detached(launch).await
+        } else {
+            self.run_attached(launch).await
         }
+    }
 
+    /// The command `start` would run for this instance.
+    pub fn launch_command(&self, instance: &InstanceConfig) -> Result<LaunchCommand> {
         let executable = self.get_executable_path()?;
-        let _config_path = self.server_path.join("server.cfg");
+        LaunchCommand::build(&self.server_path, instance, executable)
+    }
 
-        let mut command = TokioCommand::new(&executable);
+    async fn spawn_detached(&self, launch: LaunchCommand) -> Result<()> {
+        let log_path = self.new_console_log()?;
+        let log_file = std::fs::File::create(&log_path)
+            .with_context(|| format!("Failed to create console log: {:?}", log_path))?;
+        let log_file_err = log_file
+            .try_clone()
+            .context("Failed to duplicate console log handle")?;
+
+        let mut command = launch.to_command();
         command
-            .current_dir(&self.server_path)
-            .arg("+exec")
-            .arg("server.cfg")
-            .stdout(Stdio::piped())
-            .stderr(Stdio::piped());
+            .stdin(Stdio::null())
+            .stdout(log_file)
+            .stderr(log_file_err);
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::process::CommandExt;
+            // Start a new session so the server survives the controlling terminal going away
+            unsafe {
+                command.pre_exec(|| {
+                    if libc::setsid() == -1 {
+                        return Err(std::io::Error::last_os_error());
+                    }
+                    Ok(())
+                });
+            }
+        }
 
-        let child = command.spawn()
-            .with_context(|| format!("Failed to start server process: {:?}", executable))?;
+        let child = command.spawn().with_context(|| {
+            format!("Failed to start server process: {:?}", launch.executable)
+        })?;
 
-        self.process = Some(child);
+        let pid = child.id();
+        // The recorded arguments have the login token masked
+        let mut state = RuntimeState::new(pid, launch.executable.clone(), launch.display_args());
+        state.log_file = Some(log_path.clone());
+        state.save(&self.server_path)?;
+        info!("Server process started with pid {}, logging to {:?}", pid, log_path);
 
         // Wait a moment for the server to start
         tokio::time::sleep(tokio::time::Duration::from_secs(2)).await;
 
+        if !state.is_alive() {
+            RuntimeState::clear(&self.server_path)?;
+            anyhow::bail!(
+                "Server process exited during startup, see {:?} for details",
+                log_path
+            );
+        }
+
         Ok(())
     }
 
-    pub async fn stop(&mut self) -> Result<()> {
-        if let Some(mut child) = self.process.take() {
-            // Try graceful shutdown first
-            if let Ok(_) = child.kill().await {
-                // Wait for the process to exit
-                let _ = child.wait().await;
+    async fn run_attached(&self, launch: LaunchCommand) -> Result<()> {
+        let mut command = TokioCommand::from(launch.to_command());
+        command
+            .stdout(Stdio::inherit())
+            .stderr(Stdio::inherit());
+
+        let mut child = command.spawn().with_context(|| {
+            format!("Failed to start server process: {:?}", launch.executable)
+        })?;
+
+        let pid = child.id().context("Server process exited immediately")?;
+        RuntimeState::new(pid, launch.executable.clone(), launch.display_args())
+            .save(&self.server_path)?;
+        info!("Server process started with pid {}", pid);
+
+        let result = tokio::select! {
+            status = child.wait() => status.context("Failed to wait for server process"),
+            _ = signal::ctrl_c() => {
+                warn!("Received Ctrl+C, waiting for server process to exit...");
+                child.wait().await.context("Failed to wait for server process")
             }
-        }
+        };
+        RuntimeState::clear(&self.server_path)?;
 
+        let status = result?;
+        if !status.success() {
+            anyhow::bail!("Server exited with code: {:?}", status.code());
+        }
         Ok(())
     }
 
-    pub async fn get_status(&mut self) -> Result<ServerStatus> {
-        if let Some(ref mut child) = self.process {
-            match child.try_wait() {
-                Ok(Some(_)) => Ok(ServerStatus::Stopped),
-                Ok(None) => Ok(ServerStatus::Running),
-                Err(_) => Ok(ServerStatus::Stopped),
+    /// Stops the server in stages: optional countdown announced in-game, `quit` over
+    /// RCON (or SIGTERM when RCON is unavailable), then SIGTERM and finally SIGKILL
+    /// if the process is still around after each timeout.
+    pub async fn stop(&mut self, options: &StopOptions) -> Result<StopReport> {
+        let mut state = match self.running_state()? {
+            Some(state) => state,
+            None => {
+                warn!("Server is not running");
+                return Ok(StopReport {
+                    outcome: StopOutcome::NotRunning,
+                    countdown_skipped: false,
+                });
+            }
+        };
+        state.stopping_since = Some(chrono::Utc::now());
+        state.save(&self.server_path)?;
+
+        let result = tokio::select! {
+            result = self.terminate(state.pid, options) => result,
+            _ = signal::ctrl_c() => Err(anyhow::anyhow!("Interrupted while stopping the server")),
+        };
+        match result {
+            Ok(report) => {
+                RuntimeState::clear(&self.server_path)?;
+                Ok(report)
+            }
+            Err(e) => {
+                // The server may well still be running, it is no longer stopping
+                state.stopping_since = None;
+                if let Err(save_error) = state.save(&self.server_path) {
+                    warn!("Failed to reset runtime state: {:#}", save_error);
+                }
+                Err(e)
+            }
+        }
+    }
+
+    async fn terminate(&self, pid: u32, options: &StopOptions) -> Result<StopReport> {
+        let mut rcon = match RconTarget::for_instance(&self.server_path) {
+            Ok(target) => match RconClient::connect(&target, rcon::DEFAULT_TIMEOUT).await {
+                Ok(client) => Some(client),
+                Err(e) => {
+                    warn!("RCON unavailable, falling back to signals: {}", e);
+                    None
+                }
+            },
+            Err(e) => {
+                warn!("RCON not configured, falling back to signals: {}", e);
+                None
+            }
+        };
+        let countdown_skipped = options.countdown.is_some() && rcon.is_none();
+        let report = |outcome| StopReport {
+            outcome,
+            countdown_skipped,
+        };
+
+        match (rcon.as_mut(), options.countdown) {
+            (Some(client), Some(countdown)) => announce_shutdown(client, countdown).await,
+            (None, Some(_)) => warn!("Cannot announce the shutdown countdown without RCON"),
+            _ => {}
+        }
+
+        if let Some(client) = rcon.as_mut() {
+            info!("Sending quit to server process {}", pid);
+            // The server usually drops the connection before answering
+            let _ = client.exec("quit").await;
+            if self.wait_for_exit(pid, options.timeout).await {
+                return Ok(report(StopOutcome::Quit));
+            }
+            warn!("Server did not exit within {:?} after quit", options.timeout);
+        }
+
+        info!("Sending SIGTERM to server process {}", pid);
+        // Gone before the signal arrived, e.g. a late exit after quit
+        if !send_signal(pid, Signal::Term)? {
+            let outcome = match rcon {
+                Some(_) => StopOutcome::Quit,
+                None => StopOutcome::NotRunning,
+            };
+            return Ok(report(outcome));
+        }
+        if self.wait_for_exit(pid, options.timeout).await {
+            return Ok(report(StopOutcome::Terminated));
+        }
+
+        warn!("Server did not exit within {:?} after SIGTERM, killing it", options.timeout);
+        if !send_signal(pid, Signal::Kill)? {
+            return Ok(report(StopOutcome::Terminated));
+        }
+        if !self.wait_for_exit(pid, KILL_TIMEOUT).await {
+            anyhow::bail!("Server process {} did not exit after SIGKILL", pid);
+        }
+        Ok(report(StopOutcome::Killed))
+    }
+
+    pub async fn get_status(&mut self, instance: &InstanceConfig) -> Result<ServerStatus> {
+        let cfg_path = self.server_path.join("server.cfg");
+        let server_config = match cfg_path.exists() {
+            true => Some(ServerConfig::load(&cfg_path)?),
+            false => None,
+        };
+        let mode = server_config.as_ref().and_then(|cfg| cfg.game_mode());
+        let running = self.running_state()?;
+
+        // The current log, or the newest one, which usually explains why a server went down
+        let log_file = match running.as_ref().and_then(|state| state.log_file.clone()) {
+            Some(log_file) => Some(log_file),
+            None => console_logs(&self.server_path.join("logs"))?.pop(),
+        };
+        let warnings = token_warnings(instance, server_config.as_ref(), log_file.as_deref());
+        let installed_build = match AppManifest::load(&self.server_path) {
+            Ok(manifest) => manifest.map(|manifest| manifest.build_id),
+            Err(e) => {
+                warn!("{:#}", e);
+                None
+            }
+        };
+
+        let state = match running {
+            Some(state) => state,
+            None => {
+                return Ok(ServerStatus {
+                    state: ServerState::Stopped,
+                    health: Health::Offline,
+                    pid: None,
+                    uptime: None,
+                    rss_bytes: None,
+                    cpu_percent: None,
+                    port: None,
+                    port_open: false,
+                    last_log_line: log_file.as_deref().and_then(last_line),
+                    mode,
+                    info: None,
+                    installed_build,
+                    needs_update: None,
+                    warnings,
+                });
             }
+        };
+
+        let uptime = (chrono::Utc::now() - state.started_at).to_std().ok();
+        let stats = ProcessStats::sample(state.pid, CPU_SAMPLE_INTERVAL).await;
+        let (_, port) = instance_address(&self.server_path)?;
+        let info = match self.query_client().await {
+            Ok(client) => client.info().await.ok(),
+            Err(_) => None,
+        };
+
+        let in_startup = uptime.is_some_and(|uptime| uptime < STARTUP_GRACE);
+        let (server_state, health) = if state.stopping_since.is_some() {
+            (ServerState::Stopping, Health::Pending)
+        } else if info.is_some() {
+            (ServerState::Running, Health::Healthy)
+        } else if in_startup {
+            (ServerState::Starting, Health::Pending)
         } else {
-            // Check if there's a running process by looking for the executable
-            let executable = self.get_executable_path()?;
-            if executable.exists() {
-                // For now, assume stopped if no process reference
-                // TODO: Implement process detection by PID or port checking
-                Ok(ServerStatus::Stopped)
-            } else {
-                Ok(ServerStatus::Stopped)
+            (ServerState::Running, Health::Unresponsive)
+        };
+
+        Ok(ServerStatus {
+            state: server_state,
+            health,
+            pid: Some(state.pid),
+            uptime,
+            rss_bytes: stats.rss_bytes,
+            cpu_percent: stats.cpu_percent,
+            port: Some(port),
+            port_open: stats.ports.contains(&port),
+            last_log_line: log_file.as_deref().and_then(last_line),
+            mode,
+            info,
+            installed_build,
+            needs_update: None,
+            warnings,
+        })
+    }
+
+    /// Loads the persisted runtime state and verifies that the process is still alive.
+    /// Stale state left behind by a crashed or externally killed server is removed.
+    fn running_state(&self) -> Result<Option<RuntimeState>> {
+        match RuntimeState::load(&self.server_path)? {
+            Some(state) if state.is_alive() => Ok(Some(state)),
+            Some(state) => {
+                warn!("Removing stale runtime state for pid {}", state.pid);
+                RuntimeState::clear(&self.server_path)?;
+                Ok(None)
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Polls until the process exits. Returns false if it is still running after `timeout`.
+    async fn wait_for_exit(&self, pid: u32, timeout: Duration) -> bool {
+        let deadline = tokio::time::Instant::now() + timeout;
+        while process_exists(pid) {
+            if tokio::time::Instant::now() >= deadline {
+                return false;
             }
+            tokio::time::sleep(Duration::from_millis(200)).await;
         }
+        true
+    }
+
+    pub async fn query_players(&self) -> Result<Vec<PlayerInfo>> {
+        self.query_client().await?.players().await
+    }
+
+    async fn query_client(&self) -> Result<QueryClient> {
+        let (host, port) = instance_address(&self.server_path)?;
+        QueryClient::connect(&format!("{}:{}", host, port), query::DEFAULT_TIMEOUT).await
     }
 
-    pub async fn get_player_count(&self) -> Result<Option<u32>> {
-        // TODO: Implement player count retrieval via RCON or log parsing
-        Ok(None)
+    /// Creates a fresh console log path under `logs/` and prunes old logs.
+    fn new_console_log(&self) -> Result<PathBuf> {
+        let logs_dir = self.server_path.join("logs");
+        std::fs::create_dir_all(&logs_dir)
+            .with_context(|| format!("Failed to create logs directory: {:?}", logs_dir))?;
+
+        let mut logs = console_logs(&logs_dir)?;
+        while logs.len() >= CONSOLE_LOGS_KEPT {
+            let oldest = logs.remove(0);
+            if let Err(e) = std::fs::remove_file(&oldest) {
+                warn!("Failed to remove old console log {:?}: {}", oldest, e);
+            }
+        }
+
+        // Milliseconds keep quick restarts from sharing a log
+        let timestamp = chrono::Local::now().format("%Y%m%d-%H%M%S%.3f");
+        Ok(logs_dir.join(format!("console-{}.log", timestamp)))
     }
 
     fn get_executable_path(&self) -> Result<PathBuf> {
@@ -116,7 +504,7 @@ impl ServerManager {
             .with_context(|| format!("Failed to create backup directory: {:?}", backup_dir))?;
 
         // Copy configuration files
-        let config_files = ["server.cfg", "autoexec.cfg"];
+        let config_files = ["server.cfg", "autoexec.cfg", INSTANCE_FILE];
         for file in &config_files {
             let src = self.server_path.join(file);
             if src.exists() {
@@ -137,7 +525,7 @@ impl ServerManager {
         }
 
         // Restore configuration files
-        let config_files = ["server.cfg", "autoexec.cfg"];
+        let config_files = ["server.cfg", "autoexec.cfg", INSTANCE_FILE];
         for file in &config_files {
             let src = backup_dir.join(file);
             if src.exists() {
@@ -169,4 +557,147 @@ impl ServerManager {
 
         Ok(backups)
     }
-}
\ No newline at end of file
+}
+
+/// Resolves the address the instance listens on. A running server is reached at
+/// the `-ip`/`-port` it was launched with, otherwise `ip` and `hostport` from its
+/// server.cfg are used.
+pub fn instance_address(server_path: &Path) -> Result<(String, u16)> {
+    let cfg_path = server_path.join("server.cfg");
+    let state = RuntimeState::load(server_path)?;
+
+    let host = match state.as_ref().and_then(|state| state.bind_ip()) {
+        Some(ip) => Some(ip.to_string()),
+        None => ServerConfig::lookup(&cfg_path, "ip")?,
+    };
+    let host = host
+        .filter(|ip| !ip.is_empty() && ip != "0.0.0.0")
+        .unwrap_or_else(|| "127.0.0.1".to_string());
+
+    let port = match state {
+        Some(state) => state.port,
+        None => match ServerConfig::lookup(&cfg_path, "hostport")? {
+            Some(port) => port
+                .parse()
+                .with_context(|| format!("Invalid hostport value: {}", port))?,
+            None => DEFAULT_PORT,
+        },
+    };
+
+    Ok((host, port))
+}
+
+/// Flags public servers without a login token and tokens Steam rejected.
+fn token_warnings(
+    instance: &InstanceConfig,
+    server_config: Option<&ServerConfig>,
+    log_file: Option<&Path>,
+) -> Vec<String> {
+    let mut warnings = vec![];
+
+    // sv_lan defaults to 0, so servers are public unless configured otherwise
+    let public = server_config.and_then(|cfg| cfg.get("sv_lan")).unwrap_or("0") == "0";
+    if public && instance.gslt.is_none() {
+        warnings.push(
+            "Public server without a login token (GSLT), set one with `token set`".to_string(),
+        );
+    }
+
+    if instance.gslt.is_some() {
+        let log = log_file.and_then(|path| tail(path, TOKEN_LOG_SCAN_SIZE));
+        let rejected = log.as_deref().and_then(|log| {
+            log.lines()
+                .rev()
+                .find(|line| is_token_error(line))
+                .map(|line| line.trim().to_string())
+        });
+        if let Some(line) = rejected {
+            warnings.push(format!("Login token was rejected: {}", line));
+        }
+    }
+
+    warnings
+}
+
+fn is_token_error(line: &str) -> bool {
+    let line = line.to_ascii_lowercase();
+    let about_token = line.contains("gslt")
+        || line.contains("login token")
+        || line.contains("sv_setsteamaccount")
+        || line.contains("game server account");
+    about_token
+        && ["invalid", "expired", "banned", "revoked", "failure"]
+            .iter()
+            .any(|word| line.contains(word))
+}
+
+/// Reads up to `size` bytes from the end of a file.
+fn tail(path: &Path, size: u64) -> Option<String> {
+    use std::io::{Read, Seek, SeekFrom};
+
+    let mut file = std::fs::File::open(path).ok()?;
+    let len = file.metadata().ok()?.len();
+    file.seek(SeekFrom::Start(len.saturating_sub(size))).ok()?;
+    let mut tail = Vec::new();
+    file.read_to_end(&mut tail).ok()?;
+    Some(String::from_utf8_lossy(&tail).into_owned())
+}
+
+/// Returns the last non-empty line of a log file, reading only its tail.
+fn last_line(path: &Path) -> Option<String> {
+    const TAIL_SIZE: u64 = 8192;
+
+    tail(path, TAIL_SIZE)?
+        .lines()
+        .rev()
+        .map(|line| line.trim())
+        .find(|line| !line.is_empty())
+        .map(|line| line.to_string())
+}
+
+/// Returns the console logs in `logs_dir`, oldest first.
+pub fn console_logs(logs_dir: &Path) -> Result<Vec<PathBuf>> {
+    if !logs_dir.exists() {
+        return Ok(vec![]);
+    }
+
+    let mut logs = vec![];
+    for entry in std::fs::read_dir(logs_dir)
+        .with_context(|| format!("Failed to read logs directory: {:?}", logs_dir))? {
+        let path = entry?.path();
+        let is_console_log = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .is_some_and(|name| name.starts_with("console-") && name.ends_with(".log"));
+        if is_console_log {
+            logs.push(path);
+        }
+    }
+
+    // Timestamped names sort chronologically
+    logs.sort();
+    Ok(logs)
+}
+
+async fn announce_shutdown(client: &mut RconClient, countdown: u64) {
+    let mut remaining = countdown;
+    let announcements = std::iter::once(countdown).chain(
+        COUNTDOWN_ANNOUNCEMENTS
+            .iter()
+            .copied()
+            .filter(|&seconds| seconds < countdown),
+    );
+
+    for seconds in announcements {
+        tokio::time::sleep(Duration::from_secs(remaining - seconds)).await;
+        remaining = seconds;
+
+        let message = format!("say Server shutting down in {} seconds", seconds);
+        if let Err(e) = client.exec(&message).await {
+            warn!("Failed to announce shutdown: {}", e);
+            return;
+        }
+    }
+
+    tokio::time::sleep(Duration::from_secs(remaining)).await;
+}
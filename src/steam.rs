@@ -1,13 +1,32 @@
+use crate::output::OutputFormat;
 use anyhow::{Context, Result};
 use log::{error, info, warn};
-use std::io::{self, Write};
-use std::path::Path;
+use std::collections::{BTreeMap, VecDeque};
+use std::io::Write;
+use std::path::{Path, PathBuf};
 use std::process::Command;
+use std::time::Duration;
+use tokio::io::AsyncReadExt;
 use tokio::process::Command as TokioCommand;
 use tokio::signal;
 
+use crate::manifest;
+use crate::progress::ProgressReporter;
+use crate::steamcmd::{
+    downloaded_depot_dirs, downloaded_workshop_dir, SteamBranch, SteamCmdError, SteamCmdJob,
+    SteamLogin, CS2_APP_ID,
+};
+
+const PROMPT_DELAY: Duration = Duration::from_millis(300);
+/// Output lines kept to classify failures
+const CAPTURED_LINES: usize = 200;
+const USERNAME_ENV: &str = "STEAM_USERNAME";
+const PASSWORD_ENV: &str = "STEAM_PASSWORD";
+const KEYRING_SERVICE: &str = "cs2-server-cli";
+
 pub struct SteamManager {
     steam_cmd_path: Option<String>,
+    output: OutputFormat,
 }
 
 impl SteamManager {
@@ -27,208 +46,316 @@ impl SteamManager {
 
         Ok(Self {
             steam_cmd_path: Some(steam_cmd_path),
+            output: OutputFormat::default(),
         })
     }
 
-    pub async fn download_cs2_server(&self, install_path: &Path) -> Result<()> {
-        let install_path = std::fs::canonicalize(install_path)
-            .with_context(|| format!("Failed to canonicalize install path: {:?}", install_path))?;
-        info!("Downloading CS2 server files to {:?}", install_path);
+    /// Like `new`, but never installs SteamCMD. For checks that should stay
+    /// cheap and side-effect free.
+    pub fn find() -> Option<Self> {
+        Self::find_steamcmd().ok().map(|path| Self {
+            steam_cmd_path: Some(path),
+            output: OutputFormat::default(),
+        })
+    }
 
-        let steam_cmd = self
-            .steam_cmd_path
-            .as_ref()
-            .context("SteamCMD not found. Please install SteamCMD and ensure it's in your PATH.")?;
+    pub fn with_output(mut self, output: OutputFormat) -> Self {
+        self.output = output;
+        self
+    }
 
-        // CS2 AppID is 730
-        let app_id = "730";
-
-        // Create installation script
-        let script_content = format!(
-            "force_install_dir \"{}\"\n\
-             login anonymous\n\
-             app_update {} validate\n\
-             quit\n",
-            install_path.display(),
-            app_id
-        );
-
-        let script_path = install_path.join("steamscript");
-        std::fs::write(&script_path, script_content)
-            .with_context(|| format!("Failed to write Steam script: {:?}", script_path))?;
-
-        info!("Running SteamCMD to download CS2 server files");
-        // Run SteamCMD
-        let mut command = TokioCommand::new(steam_cmd);
-        command
-            .arg("+runscript")
-            .arg(&script_path)
-            .stdout(std::process::Stdio::inherit())
-            .stderr(std::process::Stdio::inherit());
+    pub async fn download_cs2_server(
+        &self,
+        install_path: &Path,
+        login: SteamLogin,
+        branch: &SteamBranch,
+    ) -> Result<()> {
+        let job = SteamCmdJob::new("download", install_path)
+            .login(login)
+            .branch(&branch.name, branch.password.as_deref())
+            .app_update(CS2_APP_ID)
+            .validate(true);
+        self.run(job).await?;
+        info!("CS2 server files downloaded successfully");
+        Ok(())
+    }
 
-        let mut child = command
-            .spawn()
-            .with_context(|| "Failed to spawn SteamCMD process")?;
+    /// Location of the shared base install of a branch, which overlay
+    /// instances link their game files from.
+    pub fn base_dir(branch: &str) -> PathBuf {
+        dirs::data_dir()
+            .unwrap_or_else(|| PathBuf::from("."))
+            .join("cs2-server-cli")
+            .join("base")
+            .join(branch)
+    }
 
-        // Handle Ctrl+C gracefully
-        tokio::select! {
-            status = child.wait() => {
-                match status {
-                    Ok(exit_status) if exit_status.success() => {
-                        info!("CS2 server files downloaded successfully");
-                    }
-                    Ok(exit_status) => {
-                        error!("SteamCMD failed with exit code: {:?}", exit_status.code());
-                        // Clean up script file
-                        let _ = std::fs::remove_file(&script_path);
-                        anyhow::bail!("SteamCMD failed with exit code: {:?}", exit_status.code());
-                    }
-                    Err(e) => {
-                        error!("Failed to wait for SteamCMD process: {}", e);
-                        let _ = std::fs::remove_file(&script_path);
-                        return Err(e.into());
-                    }
-                }
-            }
-            _ = signal::ctrl_c() => {
-                warn!("Received Ctrl+C, terminating SteamCMD process...");
-                if let Err(e) = child.kill().await {
-                    warn!("Failed to kill SteamCMD process: {}", e);
-                }
-                let _ = child.wait().await;
-                let _ = std::fs::remove_file(&script_path);
-                anyhow::bail!("Download interrupted by user");
-            }
+    /// Returns the base install of a branch, downloading it first if needed.
+    pub async fn ensure_base(&self, login: SteamLogin, branch: &SteamBranch) -> Result<PathBuf> {
+        let base = Self::base_dir(&branch.name);
+        if let Some(manifest) = manifest::AppManifest::load(&base)? {
+            info!("Using shared base {:?} (build {})", base, manifest.build_id);
+            return Ok(base);
         }
+        std::fs::create_dir_all(&base)
+            .with_context(|| format!("Failed to create base directory: {:?}", base))?;
+        info!("Downloading shared base for branch {} into {:?}", branch.name, base);
+        self.download_cs2_server(&base, login, branch).await?;
+        Ok(base)
+    }
 
-        // Clean up script file
-        if let Err(e) = std::fs::remove_file(&script_path) {
-            warn!("Failed to clean up script file: {}", e);
+    /// Updates the server files. With `validate`, SteamCMD also checks every
+    /// installed file and restores the ones that differ from the build.
+    pub async fn update_cs2_server(
+        &self,
+        install_path: &Path,
+        login: SteamLogin,
+        branch: &SteamBranch,
+        validate: bool,
+    ) -> Result<()> {
+        let action = if validate { "validate" } else { "update" };
+        let job = SteamCmdJob::new(action, install_path)
+            .login(login)
+            .branch(&branch.name, branch.password.as_deref())
+            .app_update(CS2_APP_ID)
+            .validate(validate);
+        self.run(job).await?;
+        info!("CS2 server files updated successfully");
+        Ok(())
+    }
+
+    /// Installs specific depot manifests over the server files, e.g. to roll
+    /// back to an older build. SteamCMD downloads each depot into its own
+    /// directory, from where the files are copied into the install path.
+    pub async fn install_depots(
+        &self,
+        install_path: &Path,
+        login: SteamLogin,
+        manifests: &BTreeMap<String, String>,
+    ) -> Result<()> {
+        let mut job = SteamCmdJob::new("depot download", install_path).login(login);
+        for (depot, manifest) in manifests {
+            let depot_id = depot
+                .parse()
+                .with_context(|| format!("Invalid depot ID: {}", depot))?;
+            let manifest_id = manifest
+                .parse()
+                .with_context(|| format!("Invalid manifest ID: {}", manifest))?;
+            job = job.download_depot(CS2_APP_ID, depot_id, manifest_id);
         }
+        let output = self.run(job).await?;
 
+        let depot_dirs = downloaded_depot_dirs(&output);
+        if depot_dirs.len() != manifests.len() {
+            anyhow::bail!(
+                "SteamCMD reported {} of {} depot downloads as complete",
+                depot_dirs.len(),
+                manifests.len()
+            );
+        }
+        for depot_dir in depot_dirs {
+            info!("Copying {:?} into {:?}", depot_dir, install_path);
+            copy_tree(&depot_dir, install_path)?;
+            if let Err(e) = std::fs::remove_dir_all(&depot_dir) {
+                warn!("Failed to remove depot download {:?}: {}", depot_dir, e);
+            }
+        }
+        info!("CS2 server depots installed successfully");
         Ok(())
     }
 
-    pub async fn update_cs2_server(&self, install_path: &Path) -> Result<()> {
-        let install_path = std::fs::canonicalize(install_path)
-            .with_context(|| format!("Failed to canonicalize install path: {:?}", install_path))?;
-        info!("Updating CS2 server files to {:?}", install_path);
+    /// Downloads a CS2 Workshop item below `download_dir` and returns the
+    /// directory holding its files.
+    pub async fn download_workshop_item(
+        &self,
+        download_dir: &Path,
+        login: SteamLogin,
+        item_id: u64,
+    ) -> Result<PathBuf> {
+        let job = SteamCmdJob::new("workshop download", download_dir)
+            .login(login)
+            .workshop_download(CS2_APP_ID, item_id);
+        let output = self.run(job).await?;
+        downloaded_workshop_dir(&output, item_id).with_context(|| {
+            format!(
+                "SteamCMD did not report Workshop item {} as downloaded",
+                item_id
+            )
+        })
+    }
+
+    /// Asks Steam for the current build ID of a branch of the CS2 server.
+    pub async fn latest_build_id(&self, branch: &str) -> Result<String> {
+        let job = SteamCmdJob::query("app info").app_info_print(CS2_APP_ID);
+        let output = self.run(job).await?;
+        manifest::latest_build_id(&output, branch)
+    }
+
+    /// Like `latest_build_id`, but tried once and given up after `timeout`,
+    /// for checks made in passing such as `status`.
+    pub async fn peek_latest_build_id(&self, branch: &str, timeout: Duration) -> Result<String> {
+        let job = SteamCmdJob::query("app info")
+            .app_info_print(CS2_APP_ID)
+            .background(timeout);
+        let output = self.run(job).await?;
+        manifest::latest_build_id(&output, branch)
+    }
 
+    /// Runs a SteamCMD job to completion, retrying failures that look
+    /// transient, and returns the captured output. Ctrl+C or SIGTERM kills
+    /// SteamCMD and aborts the job.
+    pub async fn run(&self, mut job: SteamCmdJob) -> Result<Vec<String>> {
+        if let Some(install_dir) = &job.install_dir {
+            let install_dir = std::fs::canonicalize(install_dir).with_context(|| {
+                format!("Failed to canonicalize install path: {:?}", install_dir)
+            })?;
+            job.install_dir = Some(install_dir);
+        }
         let steam_cmd = self
             .steam_cmd_path
             .as_ref()
             .context("SteamCMD not found. Please install SteamCMD and ensure it's in your PATH.")?;
 
-        let app_id = "730";
+        let mut attempt = 0;
+        loop {
+            attempt += 1;
+            info!(
+                "Running SteamCMD {} (attempt {}/{})",
+                job.action,
+                attempt,
+                job.retries + 1
+            );
+            let (code, output) = self.run_once(steam_cmd, &job).await?;
+            let Some(failure) = SteamCmdError::classify(&output, code) else {
+                return Ok(output);
+            };
 
-        let script_content = format!(
-            "force_install_dir \"{}\"\n\
-             login anonymous\n\
-             app_update {} validate\n\
-             quit\n",
-            install_path.display(),
-            app_id
-        );
+            if failure.is_transient() && attempt <= job.retries {
+                let delay = failure.retry_delay(attempt);
+                warn!(
+                    "SteamCMD {} failed: {}, retrying in {}s",
+                    job.action,
+                    failure,
+                    delay.as_secs()
+                );
+                tokio::time::sleep(delay).await;
+                continue;
+            }
+            error!("SteamCMD {} failed: {}", job.action, failure);
+            if job.hints {
+                eprintln!("Hint: {}", failure.guidance());
+            }
+            return Err(anyhow::Error::new(failure))
+                .with_context(|| format!("SteamCMD {} failed", job.action));
+        }
+    }
 
-        let script_path = install_path.join("steamscript");
-        std::fs::write(&script_path, script_content)
-            .with_context(|| format!("Failed to write Steam script: {:?}", script_path))?;
+    /// Runs SteamCMD once and returns its exit code along with the last
+    /// lines of its output, or all of it for quiet jobs.
+    async fn run_once(
+        &self,
+        steam_cmd: &str,
+        job: &SteamCmdJob,
+    ) -> Result<(Option<i32>, Vec<String>)> {
+        // The script may contain a password. Temporary files are created
+        // readable by the owner only and are deleted when dropped, on every
+        // exit path.
+        let mut script = tempfile::Builder::new()
+            .prefix("steamcmd-")
+            .suffix(".txt")
+            .tempfile()
+            .context("Failed to create Steam script")?;
+        script
+            .write_all(job.script().as_bytes())
+            .and_then(|_| script.flush())
+            .context("Failed to write Steam script")?;
 
-        info!("Running SteamCMD to update CS2 server files");
         let mut command = TokioCommand::new(steam_cmd);
         command
             .arg("+runscript")
-            .arg(&script_path)
-            .stdout(std::process::Stdio::inherit())
-            .stderr(std::process::Stdio::inherit());
-
+            .arg(script.path())
+            .stdout(std::process::Stdio::piped())
+            .stderr(std::process::Stdio::inherit())
+            .kill_on_drop(true);
+        // steamcmd.sh runs the real binary as a child, so give it its own
+        // process group that can be killed as a whole
+        #[cfg(unix)]
+        command.process_group(0);
         let mut child = command
             .spawn()
             .with_context(|| "Failed to spawn SteamCMD process")?;
-
-        // Handle Ctrl+C gracefully
-        tokio::select! {
-            status = child.wait() => {
-                match status {
-                    Ok(exit_status) if exit_status.success() => {
-                        info!("CS2 server files updated successfully");
+        let mut stdout = child.stdout.take().context("SteamCMD stdout not captured")?;
+
+        let mut reporter = ProgressReporter::new(self.output);
+        let mut buffer = [0u8; 8192];
+        let mut pending: Vec<u8> = vec![];
+        let mut captured: VecDeque<String> = VecDeque::new();
+        let shutdown = shutdown_signal();
+        tokio::pin!(shutdown);
+        let deadline = job
+            .timeout
+            .map(|timeout| tokio::time::Instant::now() + timeout);
+        loop {
+            tokio::select! {
+                read = stdout.read(&mut buffer) => {
+                    let read = read.with_context(|| "Failed to read SteamCMD output")?;
+                    if read == 0 {
+                        break;
                     }
-                    Ok(exit_status) => {
-                        error!("SteamCMD update failed with exit code: {:?}", exit_status.code());
-                        let _ = std::fs::remove_file(&script_path);
-                        anyhow::bail!("SteamCMD update failed with exit code: {:?}", exit_status.code());
-                    }
-                    Err(e) => {
-                        error!("Failed to wait for SteamCMD process: {}", e);
-                        let _ = std::fs::remove_file(&script_path);
-                        return Err(e.into());
+                    pending.extend_from_slice(&buffer[..read]);
+                    while let Some(end) = pending.iter().position(|b| *b == b'\n') {
+                        let line: Vec<u8> = pending.drain(..=end).collect();
+                        let line = String::from_utf8_lossy(&line);
+                        let line = line.trim_end_matches(['\r', '\n']);
+                        if !job.quiet {
+                            reporter.line(line);
+                            if captured.len() == CAPTURED_LINES {
+                                captured.pop_front();
+                            }
+                        }
+                        captured.push_back(line.to_string());
                     }
                 }
-            }
-            _ = signal::ctrl_c() => {
-                warn!("Received Ctrl+C, terminating SteamCMD process...");
-                if let Err(e) = child.kill().await {
-                    warn!("Failed to kill SteamCMD process: {}", e);
+                // Prompts such as the Steam Guard code are not newline
+                // terminated, show them once SteamCMD goes quiet
+                _ = tokio::time::sleep(PROMPT_DELAY), if !pending.is_empty() && !job.quiet => {
+                    reporter.partial(&String::from_utf8_lossy(&pending));
+                    pending.clear();
+                }
+                _ = &mut shutdown => {
+                    reporter.finish();
+                    warn!("Received interrupt, terminating SteamCMD process...");
+                    kill_steamcmd(&mut child).await;
+                    anyhow::bail!("SteamCMD {} interrupted by user", job.action);
+                }
+                // The branch is disabled without a deadline, the sleep is never polled
+                _ = tokio::time::sleep_until(deadline.unwrap_or_else(tokio::time::Instant::now)),
+                    if deadline.is_some() =>
+                {
+                    reporter.finish();
+                    kill_steamcmd(&mut child).await;
+                    anyhow::bail!(
+                        "SteamCMD {} timed out after {}s",
+                        job.action,
+                        job.timeout.unwrap_or_default().as_secs()
+                    );
                 }
-                let _ = child.wait().await;
-                let _ = std::fs::remove_file(&script_path);
-                anyhow::bail!("Update interrupted by user");
             }
         }
-
-        let _ = std::fs::remove_file(&script_path);
-        Ok(())
-    }
-
-    pub async fn download_with_credentials(
-        &self,
-        install_path: &Path,
-        username: &str,
-        password: &str,
-    ) -> Result<()> {
-        let install_path = std::fs::canonicalize(install_path)
-            .with_context(|| format!("Failed to canonicalize install path: {:?}", install_path))?;
-        println!("Downloading CS2 server files with authentication...");
-
-        let steam_cmd = self.steam_cmd_path.as_ref().context("SteamCMD not found")?;
-
-        let app_id = "730";
-
-        let script_content = format!(
-            "force_install_dir \"{}\"\n\
-             login {} {}\n\
-             app_update {} validate\n\
-             quit\n",
-            install_path.display(),
-            username,
-            password,
-            app_id
-        );
-
-        let script_path = install_path.join("steamscript");
-        std::fs::write(&script_path, script_content)
-            .with_context(|| format!("Failed to write Steam script: {:?}", script_path))?;
-
-        let mut command = TokioCommand::new(steam_cmd);
-        command
-            .arg("+runscript")
-            .arg(&script_path)
-            .stdout(std::process::Stdio::inherit())
-            .stderr(std::process::Stdio::inherit());
-
-        let status = command
-            .status()
-            .await
-            .with_context(|| "Failed to execute SteamCMD")?;
-
-        if !status.success() {
-            anyhow::bail!("SteamCMD failed with exit code: {:?}", status.code());
+        if !pending.is_empty() {
+            let line = String::from_utf8_lossy(&pending);
+            if !job.quiet {
+                reporter.line(&line);
+            }
+            captured.push_back(line.into_owned());
         }
+        reporter.finish();
 
-        let _ = std::fs::remove_file(&script_path);
-
-        println!("CS2 server files downloaded successfully");
-        Ok(())
+        let status = child
+            .wait()
+            .await
+            .with_context(|| "Failed to wait for SteamCMD process")?;
+        Ok((status.code(), captured.into()))
     }
 
     pub fn install_steamcmd() -> Result<String> {
@@ -337,37 +464,138 @@ impl SteamManager {
         Err(anyhow::anyhow!("SteamCMD not found"))
     }
 
-    pub fn prompt_credentials() -> Result<(String, String)> {
-        println!("Steam credentials required for CS2 server installation.");
-        println!("Note: Anonymous login may work for basic installation, but authenticated login is recommended.");
-        println!();
+    /// Works out how to log in to Steam. Without a username the login is
+    /// anonymous, which is enough for the CS2 dedicated server. The password
+    /// is taken from STEAM_PASSWORD, then the Secret Service keyring, and
+    /// only prompted for as a last resort.
+    pub fn steam_login(username: Option<&str>) -> Result<SteamLogin> {
+        let username = match username {
+            Some(username) => username.to_string(),
+            None => match std::env::var(USERNAME_ENV) {
+                Ok(username) if !username.is_empty() => username,
+                _ => return Ok(SteamLogin::Anonymous),
+            },
+        };
+        if username == "anonymous" {
+            return Ok(SteamLogin::Anonymous);
+        }
+
+        let password = match std::env::var(PASSWORD_ENV) {
+            Ok(password) if !password.is_empty() => {
+                info!("Using Steam password from {}", PASSWORD_ENV);
+                password
+            }
+            _ => match Self::keyring_password(&username) {
+                Some(password) => {
+                    info!("Using Steam password from the keyring");
+                    password
+                }
+                None => rpassword::prompt_password(format!("Steam password for {}: ", username))
+                    .context("Failed to read password")?,
+            },
+        };
+
+        info!("Using authenticated Steam login for user: {}", username);
+        Ok(SteamLogin::Account { username, password })
+    }
 
-        print!("Steam Username: ");
-        io::stdout()
-            .flush()
-            .with_context(|| "Failed to flush stdout")?;
+    /// Looks the password up with `secret-tool`, as stored by
+    /// `secret-tool store --label=Steam service cs2-server-cli account <username>`.
+    fn keyring_password(username: &str) -> Option<String> {
+        let output = Command::new("secret-tool")
+            .args(["lookup", "service", KEYRING_SERVICE, "account", username])
+            .stderr(std::process::Stdio::null())
+            .output()
+            .ok()?;
+        if !output.status.success() {
+            return None;
+        }
+        let password = String::from_utf8(output.stdout).ok()?;
+        let password = password.trim_end_matches('\n');
+        (!password.is_empty()).then(|| password.to_string())
+    }
+}
 
-        let mut username = String::new();
-        io::stdin()
-            .read_line(&mut username)
-            .with_context(|| "Failed to read username")?;
-        let username = username.trim().to_string();
+/// Copies the contents of `from` into `to`, overwriting existing files.
+/// Files that are already identical are left alone, so that they keep their
+/// modification time.
+fn copy_tree(from: &Path, to: &Path) -> Result<()> {
+    std::fs::create_dir_all(to)
+        .with_context(|| format!("Failed to create directory: {:?}", to))?;
+    for entry in
+        std::fs::read_dir(from).with_context(|| format!("Failed to read directory: {:?}", from))?
+    {
+        let entry = entry?;
+        let target = to.join(entry.file_name());
+        if entry.file_type()?.is_dir() {
+            copy_tree(&entry.path(), &target)?;
+        } else if !same_contents(&entry.path(), &target)? {
+            // The target may be a hard link into a shared base install, so
+            // replace it instead of writing through it
+            if target.symlink_metadata().is_ok() {
+                std::fs::remove_file(&target)
+                    .with_context(|| format!("Failed to replace {:?}", target))?;
+            }
+            std::fs::copy(entry.path(), &target)
+                .with_context(|| format!("Failed to copy {:?} to {:?}", entry.path(), target))?;
+        }
+    }
+    Ok(())
+}
 
-        print!("Steam Password: ");
-        io::stdout()
-            .flush()
-            .with_context(|| "Failed to flush stdout")?;
+fn same_contents(a: &Path, b: &Path) -> Result<bool> {
+    use std::io::Read;
 
-        let password = rpassword::read_password().with_context(|| "Failed to read password")?;
+    let (Ok(a_meta), Ok(b_meta)) = (std::fs::metadata(a), std::fs::metadata(b)) else {
+        return Ok(false);
+    };
+    if a_meta.len() != b_meta.len() {
+        return Ok(false);
+    }
+    let mut a_file = std::fs::File::open(a).with_context(|| format!("Failed to open {:?}", a))?;
+    let mut b_file = std::fs::File::open(b).with_context(|| format!("Failed to open {:?}", b))?;
+    let mut a_buffer = [0u8; 64 * 1024];
+    let mut b_buffer = [0u8; 64 * 1024];
+    loop {
+        let read = a_file
+            .read(&mut a_buffer)
+            .with_context(|| format!("Failed to read {:?}", a))?;
+        if read == 0 {
+            return Ok(true);
+        }
+        b_file
+            .read_exact(&mut b_buffer[..read])
+            .with_context(|| format!("Failed to read {:?}", b))?;
+        if a_buffer[..read] != b_buffer[..read] {
+            return Ok(false);
+        }
+    }
+}
 
-        println!(); // New line after password input
+/// Kills SteamCMD along with the children steamcmd.sh started.
+async fn kill_steamcmd(child: &mut tokio::process::Child) {
+    #[cfg(unix)]
+    if let Some(pid) = child.id() {
+        unsafe { libc::kill(-(pid as libc::pid_t), libc::SIGKILL) };
+    }
+    if let Err(e) = child.kill().await {
+        warn!("Failed to kill SteamCMD process: {}", e);
+    }
+    let _ = child.wait().await;
+}
 
-        if username.is_empty() {
-            warn!("Empty username provided, falling back to anonymous login");
-            Ok(("anonymous".to_string(), "".to_string()))
-        } else {
-            info!("Using authenticated Steam login for user: {}", username);
-            Ok((username, password))
+/// Resolves on Ctrl+C, or on SIGTERM where available.
+async fn shutdown_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal as unix_signal, SignalKind};
+        if let Ok(mut terminate) = unix_signal(SignalKind::terminate()) {
+            tokio::select! {
+                _ = signal::ctrl_c() => {}
+                _ = terminate.recv() => {}
+            }
+            return;
         }
     }
+    let _ = signal::ctrl_c().await;
 }
@@ -0,0 +1,704 @@
+use std::fmt::{self, Write as _};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use thiserror::Error;
+
+/// Steam application ID of the CS2 dedicated server.
+pub const CS2_APP_ID: u32 = 730;
+pub const PUBLIC_BRANCH: &str = "public";
+
+#[derive(Clone)]
+pub enum SteamLogin {
+    Anonymous,
+    Account { username: String, password: String },
+}
+
+// Keeps the password out of logs and error messages
+impl fmt::Debug for SteamLogin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SteamLogin::Anonymous => write!(f, "Anonymous"),
+            SteamLogin::Account { username, .. } => write!(f, "Account({})", username),
+        }
+    }
+}
+
+/// A Steam branch and the password of a private beta.
+#[derive(Clone)]
+pub struct SteamBranch {
+    pub name: String,
+    pub password: Option<String>,
+}
+
+impl SteamBranch {
+    pub fn new(name: Option<&str>, password: Option<&str>) -> Self {
+        Self {
+            name: name.unwrap_or(PUBLIC_BRANCH).to_string(),
+            password: password.map(str::to_string),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum SteamCmdCommand {
+    AppUpdate { app_id: u32 },
+    AppInfoPrint { app_id: u32 },
+    DownloadDepot {
+        app_id: u32,
+        depot_id: u64,
+        manifest_id: u64,
+    },
+    WorkshopDownload { app_id: u32, item_id: u64 },
+}
+
+/// One SteamCMD session: log in, run a list of commands against an install
+/// directory and quit. Not Debug, as it may carry passwords.
+#[derive(Clone)]
+pub struct SteamCmdJob {
+    /// Short name of the operation, used in log and error messages
+    pub action: &'static str,
+    /// Not needed for jobs that only query Steam
+    pub install_dir: Option<PathBuf>,
+    pub login: SteamLogin,
+    pub commands: Vec<SteamCmdCommand>,
+    pub validate: bool,
+    pub branch: Option<String>,
+    pub branch_password: Option<String>,
+    /// How many times a failed run is retried when the failure looks transient
+    pub retries: u32,
+    /// Capture all output instead of showing it, for jobs that are parsed
+    pub quiet: bool,
+    /// Kill SteamCMD and fail once a run takes longer than this
+    pub timeout: Option<Duration>,
+    /// Print guidance on how to fix a failure, off for background checks
+    pub hints: bool,
+}
+
+impl SteamCmdJob {
+    pub fn new(action: &'static str, install_dir: &Path) -> Self {
+        Self {
+            action,
+            install_dir: Some(install_dir.to_path_buf()),
+            login: SteamLogin::Anonymous,
+            commands: vec![],
+            validate: false,
+            branch: None,
+            branch_password: None,
+            retries: 2,
+            quiet: false,
+            timeout: None,
+            hints: true,
+        }
+    }
+
+    /// A job that only asks Steam for information and installs nothing.
+    pub fn query(action: &'static str) -> Self {
+        Self {
+            install_dir: None,
+            quiet: true,
+            ..Self::new(action, Path::new(""))
+        }
+    }
+
+    /// A single attempt that gives up after `timeout` and fails silently, for
+    /// checks that must not hold up the command that asked.
+    pub fn background(mut self, timeout: Duration) -> Self {
+        self.retries = 0;
+        self.timeout = Some(timeout);
+        self.hints = false;
+        self
+    }
+
+    pub fn login(mut self, login: SteamLogin) -> Self {
+        self.login = login;
+        self
+    }
+
+    pub fn app_update(mut self, app_id: u32) -> Self {
+        self.commands.push(SteamCmdCommand::AppUpdate { app_id });
+        self
+    }
+
+    pub fn app_info_print(mut self, app_id: u32) -> Self {
+        self.commands.push(SteamCmdCommand::AppInfoPrint { app_id });
+        self
+    }
+
+    pub fn workshop_download(mut self, app_id: u32, item_id: u64) -> Self {
+        self.commands
+            .push(SteamCmdCommand::WorkshopDownload { app_id, item_id });
+        self
+    }
+
+    pub fn validate(mut self, validate: bool) -> Self {
+        self.validate = validate;
+        self
+    }
+
+    pub fn download_depot(mut self, app_id: u32, depot_id: u64, manifest_id: u64) -> Self {
+        self.commands.push(SteamCmdCommand::DownloadDepot {
+            app_id,
+            depot_id,
+            manifest_id,
+        });
+        self
+    }
+
+    pub fn branch(mut self, branch: &str, password: Option<&str>) -> Self {
+        self.branch = Some(branch.to_string());
+        self.branch_password = password.map(str::to_string);
+        self
+    }
+
+    /// Renders the job as a SteamCMD script. The install directory has to be
+    /// set before logging in, otherwise SteamCMD ignores it.
+    pub fn script(&self) -> String {
+        let mut script = String::new();
+        if let Some(install_dir) = &self.install_dir {
+            let _ = writeln!(
+                script,
+                "force_install_dir {}",
+                quote(&install_dir.display().to_string())
+            );
+        }
+        match &self.login {
+            SteamLogin::Anonymous => script.push_str("login anonymous\n"),
+            SteamLogin::Account { username, password } => {
+                let _ = writeln!(script, "login {} {}", quote(username), quote(password));
+            }
+        }
+        for command in &self.commands {
+            match command {
+                SteamCmdCommand::AppUpdate { app_id } => {
+                    let _ = write!(script, "app_update {}", app_id);
+                    // The branch is always passed, otherwise SteamCMD stays on
+                    // whichever beta was installed last
+                    if let Some(branch) = &self.branch {
+                        let _ = write!(script, " -beta {}", quote(branch));
+                        if let Some(password) = &self.branch_password {
+                            let _ = write!(script, " -betapassword {}", quote(password));
+                        }
+                    }
+                    if self.validate {
+                        script.push_str(" validate");
+                    }
+                    script.push('\n');
+                }
+                SteamCmdCommand::AppInfoPrint { app_id } => {
+                    // Without the update SteamCMD prints its cached, possibly stale, info
+                    script.push_str("app_info_update 1\n");
+                    let _ = writeln!(script, "app_info_print {}", app_id);
+                }
+                SteamCmdCommand::DownloadDepot {
+                    app_id,
+                    depot_id,
+                    manifest_id,
+                } => {
+                    let _ = writeln!(
+                        script,
+                        "download_depot {} {} {}",
+                        app_id, depot_id, manifest_id
+                    );
+                }
+                SteamCmdCommand::WorkshopDownload { app_id, item_id } => {
+                    let _ = write!(script, "workshop_download_item {} {}", app_id, item_id);
+                    if self.validate {
+                        script.push_str(" validate");
+                    }
+                    script.push('\n');
+                }
+            }
+        }
+        script.push_str("quit\n");
+        script
+    }
+}
+
+/// Why a SteamCMD run failed, worked out from its output and exit code.
+#[derive(Debug, PartialEq, Error)]
+pub enum SteamCmdError {
+    #[error("not enough disk space (update state {state})")]
+    DiskSpace { state: String },
+    #[error("the download timed out (update state {state})")]
+    Timeout { state: String },
+    #[error("the app update failed (update state {state})")]
+    UpdateFailed { state: String },
+    #[error("the account does not own the app (No subscription)")]
+    NoSubscription,
+    #[error("Steam is rate limiting logins")]
+    RateLimited,
+    #[error("invalid Steam username or password")]
+    InvalidPassword,
+    #[error("Steam Guard code missing or rejected")]
+    SteamGuard,
+    #[error("could not connect to Steam")]
+    NoConnection,
+    #[error("SteamCMD exited with code {code}")]
+    Exit { code: i32 },
+    #[error("SteamCMD was terminated by a signal")]
+    Terminated,
+}
+
+impl SteamCmdError {
+    /// Classifies a finished run. Output is checked first because SteamCMD
+    /// does not always exit with an error code when an update fails. A clean
+    /// exit that reports the app as installed is a success, whatever errors
+    /// SteamCMD recovered from on the way.
+    pub fn classify(output: &[String], code: Option<i32>) -> Option<Self> {
+        if code == Some(0) && output.iter().any(|line| is_success_line(line)) {
+            return None;
+        }
+        // The last error printed is the one that ended the run
+        let from_output = output.iter().rev().find_map(|line| Self::from_line(line));
+        match (from_output, code) {
+            (Some(error), _) => Some(error),
+            (None, Some(0)) => None,
+            (None, Some(code)) => Some(SteamCmdError::Exit { code }),
+            (None, None) => Some(SteamCmdError::Terminated),
+        }
+    }
+
+    fn from_line(line: &str) -> Option<Self> {
+        let line = line.trim();
+        // e.g. "ERROR! Failed to install app '730' (No subscription)"
+        if line.contains("(No subscription)") {
+            return Some(SteamCmdError::NoSubscription);
+        }
+        // e.g. "Error! App '730' state is 0x202 after update job."
+        if let Some(rest) = line.strip_prefix("Error! App '") {
+            if let Some((_, rest)) = rest.split_once("state is ") {
+                let state = rest.split_whitespace().next()?.to_string();
+                return Some(match state.as_str() {
+                    "0x202" => SteamCmdError::DiskSpace { state },
+                    "0x402" | "0x602" => SteamCmdError::Timeout { state },
+                    _ => SteamCmdError::UpdateFailed { state },
+                });
+            }
+            if rest.contains("No subscription") {
+                return Some(SteamCmdError::NoSubscription);
+            }
+            return None;
+        }
+        // Login failures, e.g. "FAILED (Rate Limit Exceeded)"
+        let (_, reason) = line.split_once("FAILED (")?;
+        let reason = reason.trim_end_matches(')');
+        match reason {
+            "Rate Limit Exceeded" => Some(SteamCmdError::RateLimited),
+            "Invalid Password" => Some(SteamCmdError::InvalidPassword),
+            "Two-factor code mismatch" | "Account Logon Denied" | "Invalid Login Auth Code" => {
+                Some(SteamCmdError::SteamGuard)
+            }
+            "No Connection" | "Timeout" | "Service Unavailable" => {
+                Some(SteamCmdError::NoConnection)
+            }
+            _ => None,
+        }
+    }
+
+    /// Whether running the same job again is likely to succeed.
+    pub fn is_transient(&self) -> bool {
+        match self {
+            SteamCmdError::Timeout { .. }
+            | SteamCmdError::UpdateFailed { .. }
+            | SteamCmdError::RateLimited
+            | SteamCmdError::NoConnection => true,
+            // 7 is common right after SteamCMD updated itself, 8 is a
+            // failed app update without a more specific message
+            SteamCmdError::Exit { code } => matches!(code, 1 | 3 | 6 | 7 | 8),
+            SteamCmdError::DiskSpace { .. }
+            | SteamCmdError::NoSubscription
+            | SteamCmdError::InvalidPassword
+            | SteamCmdError::SteamGuard
+            | SteamCmdError::Terminated => false,
+        }
+    }
+
+    /// Delay before the given retry, doubling each time. Rate limits take
+    /// much longer to clear than network hiccups.
+    pub fn retry_delay(&self, attempt: u32) -> Duration {
+        let base = match self {
+            SteamCmdError::RateLimited => Duration::from_secs(60),
+            _ => Duration::from_secs(5),
+        };
+        base * 2u32.pow(attempt.saturating_sub(1).min(5))
+    }
+
+    /// What the user can do about a failure that retrying does not fix.
+    pub fn guidance(&self) -> &'static str {
+        match self {
+            SteamCmdError::DiskSpace { .. } => {
+                "Free up disk space; a full CS2 server install needs about 60 GB."
+            }
+            SteamCmdError::Timeout { .. }
+            | SteamCmdError::UpdateFailed { .. }
+            | SteamCmdError::NoConnection => {
+                "Check the network connection to Steam and run the command again."
+            }
+            SteamCmdError::NoSubscription => {
+                "The app is not available to this account. The CS2 server only needs anonymous login, try without --steam-user."
+            }
+            SteamCmdError::RateLimited => "Wait a while before logging in to Steam again.",
+            SteamCmdError::InvalidPassword => {
+                "Check the Steam username and the password in STEAM_PASSWORD or the keyring."
+            }
+            SteamCmdError::SteamGuard => {
+                "Run the command in a terminal to enter the Steam Guard code, or use anonymous login."
+            }
+            SteamCmdError::Exit { .. } => "See the SteamCMD output above for details.",
+            SteamCmdError::Terminated => "SteamCMD was killed, possibly by the OOM killer.",
+        }
+    }
+}
+
+/// e.g. "Success! App '730' fully installed." or "Success! App '730' already up to date."
+fn is_success_line(line: &str) -> bool {
+    line.trim()
+        .strip_prefix("Success! App '")
+        .is_some_and(|rest| rest.contains("fully installed") || rest.contains("already up to date"))
+}
+
+/// Directories reported by lines like
+/// `Depot download complete : "/steam/steamapps/content/app_730/depot_2347771" (1234 files, manifest 5678)`
+pub fn downloaded_depot_dirs(output: &[String]) -> Vec<PathBuf> {
+    output
+        .iter()
+        .filter_map(|line| {
+            let rest = line.trim().strip_prefix("Depot download complete")?;
+            let rest = rest.trim_start().strip_prefix(':')?.trim_start();
+            let (path, _) = rest.strip_prefix('"')?.split_once('"')?;
+            Some(PathBuf::from(path))
+        })
+        .collect()
+}
+
+/// Directory reported by a line like
+/// `Success. Downloaded item 3070244931 to "/steam/steamapps/workshop/content/730/3070244931" (52428800 bytes)`
+pub fn downloaded_workshop_dir(output: &[String], item_id: u64) -> Option<PathBuf> {
+    let prefix = format!("Success. Downloaded item {} to", item_id);
+    output.iter().find_map(|line| {
+        let rest = line.trim().strip_prefix(&prefix)?.trim_start();
+        let (path, _) = rest.strip_prefix('"')?.split_once('"')?;
+        Some(PathBuf::from(path))
+    })
+}
+
+fn quote(value: &str) -> String {
+    format!("\"{}\"", value.replace('"', "\\\""))
+}
+
+/// One "Update state" line of SteamCMD output, e.g.
+/// ` Update state (0x61) downloading, progress: 45.12 (13958094564 / 30938473984)`
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProgressLine {
+    pub state_code: u32,
+    pub state: String,
+    pub percent: f64,
+    pub bytes_done: u64,
+    pub bytes_total: u64,
+}
+
+pub fn parse_progress(line: &str) -> Option<ProgressLine> {
+    let rest = line.trim().strip_prefix("Update state (0x")?;
+    let (code, rest) = rest.split_once(')')?;
+    let state_code = u32::from_str_radix(code, 16).ok()?;
+    let (state, rest) = rest.trim_start().split_once(',')?;
+    let rest = rest.trim_start().strip_prefix("progress:")?.trim_start();
+    let (percent, rest) = rest.split_once(' ')?;
+    let (done, total) = rest
+        .trim()
+        .strip_prefix('(')?
+        .strip_suffix(')')?
+        .split_once('/')?;
+    Some(ProgressLine {
+        state_code,
+        state: state.trim().to_string(),
+        percent: percent.parse().ok()?,
+        bytes_done: done.trim().parse().ok()?,
+        bytes_total: total.trim().parse().ok()?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn update_script() {
+        let job = SteamCmdJob::new("update", Path::new("/srv/cs2"))
+            .branch("beta", Some("pass \"word\""))
+            .app_update(CS2_APP_ID)
+            .validate(true);
+        assert_eq!(
+            job.script(),
+            "force_install_dir \"/srv/cs2\"\n\
+             login anonymous\n\
+             app_update 730 -beta \"beta\" -betapassword \"pass \\\"word\\\"\" validate\n\
+             quit\n"
+        );
+    }
+
+    #[test]
+    fn workshop_download_script() {
+        let login = SteamLogin::Account {
+            username: "admin".to_string(),
+            password: "secret".to_string(),
+        };
+        let job = SteamCmdJob::new("workshop download", Path::new("/tmp/workshop"))
+            .login(login)
+            .workshop_download(CS2_APP_ID, 3070244931);
+        assert_eq!(
+            job.script(),
+            "force_install_dir \"/tmp/workshop\"\n\
+             login \"admin\" \"secret\"\n\
+             workshop_download_item 730 3070244931\n\
+             quit\n"
+        );
+        assert!(job
+            .validate(true)
+            .script()
+            .contains("workshop_download_item 730 3070244931 validate\n"));
+    }
+
+    #[test]
+    fn query_script_has_no_install_dir() {
+        let job = SteamCmdJob::query("app info").app_info_print(CS2_APP_ID);
+        assert_eq!(
+            job.script(),
+            "login anonymous\napp_info_update 1\napp_info_print 730\nquit\n"
+        );
+    }
+
+    #[test]
+    fn finds_downloaded_workshop_dir() {
+        let output = lines(&[
+            "Downloading item 3070244931 ...",
+            "Success. Downloaded item 3070244931 to \"/tmp/workshop/steamapps/workshop/content/730/3070244931\" (52428800 bytes) ",
+        ]);
+        assert_eq!(
+            downloaded_workshop_dir(&output, 3070244931),
+            Some(PathBuf::from(
+                "/tmp/workshop/steamapps/workshop/content/730/3070244931"
+            ))
+        );
+        assert_eq!(downloaded_workshop_dir(&output, 1), None);
+        let failed = lines(&["ERROR! Download item 3070244931 failed (Failure)."]);
+        assert_eq!(downloaded_workshop_dir(&failed, 3070244931), None);
+    }
+
+    #[test]
+    fn classifies_captured_output() {
+        use SteamCmdError::*;
+
+        // Output, exit code, expected failure, retried
+        type Case<'a> = (&'a [&'a str], Option<i32>, Option<SteamCmdError>, bool);
+        let installed = "Success! App '730' fully installed.";
+        let cases: &[Case] = &[
+            (&[installed], Some(0), None, false),
+            (
+                &["Success! App '730' already up to date."],
+                Some(0),
+                None,
+                false,
+            ),
+            // Errors SteamCMD recovered from do not count once it reports success
+            (
+                &[
+                    "Logging in user 'anonymous' to Steam Public...FAILED (No Connection)",
+                    "Logging in user 'anonymous' to Steam Public...OK",
+                    installed,
+                ],
+                Some(0),
+                None,
+                false,
+            ),
+            (&[installed], Some(8), Some(Exit { code: 8 }), true),
+            (&[installed], Some(5), Some(Exit { code: 5 }), false),
+            (
+                &["Loading Steam API...OK"],
+                Some(7),
+                Some(Exit { code: 7 }),
+                true,
+            ),
+            (&["Loading Steam API...OK"], None, Some(Terminated), false),
+            (
+                &["Logging in user 'admin' to Steam Public...FAILED (Rate Limit Exceeded)"],
+                Some(5),
+                Some(RateLimited),
+                true,
+            ),
+            (
+                &[
+                    " Update state (0x61) downloading, progress: 99.80 (100 / 101)",
+                    "Error! App '730' state is 0x202 after update job.",
+                ],
+                Some(8),
+                Some(DiskSpace {
+                    state: "0x202".to_string(),
+                }),
+                false,
+            ),
+            // Not every failed update exits with an error code
+            (
+                &["Error! App '730' state is 0x402 after update job."],
+                Some(0),
+                Some(Timeout {
+                    state: "0x402".to_string(),
+                }),
+                true,
+            ),
+            (
+                &["Error! App '730' state is 0x606 after update job."],
+                Some(8),
+                Some(UpdateFailed {
+                    state: "0x606".to_string(),
+                }),
+                true,
+            ),
+            (
+                &["Logging in user 'admin' to Steam Public...FAILED (Invalid Password)"],
+                Some(5),
+                Some(InvalidPassword),
+                false,
+            ),
+            (
+                &["Logging in user 'admin' to Steam Public...FAILED (Account Logon Denied)"],
+                Some(5),
+                Some(SteamGuard),
+                false,
+            ),
+            (
+                &["Logging in user 'admin' to Steam Public...FAILED (Two-factor code mismatch)"],
+                Some(5),
+                Some(SteamGuard),
+                false,
+            ),
+            (
+                &["Logging in user 'anonymous' to Steam Public...FAILED (Timeout)"],
+                Some(5),
+                Some(NoConnection),
+                true,
+            ),
+            (
+                &["ERROR! Failed to install app '730' (No subscription)"],
+                Some(8),
+                Some(NoSubscription),
+                false,
+            ),
+        ];
+
+        for (output, code, expected, retried) in cases {
+            let failure = SteamCmdError::classify(&lines(output), *code);
+            assert_eq!(
+                failure.as_ref(),
+                expected.as_ref(),
+                "{:?} exiting with {:?}",
+                output,
+                code
+            );
+            assert_eq!(
+                failure.as_ref().is_some_and(SteamCmdError::is_transient),
+                *retried,
+                "{:?} exiting with {:?}",
+                output,
+                code
+            );
+        }
+    }
+
+    #[test]
+    fn retry_delays_grow() {
+        let timeout = SteamCmdError::Timeout {
+            state: "0x402".to_string(),
+        };
+        assert_eq!(timeout.retry_delay(1), Duration::from_secs(5));
+        assert_eq!(timeout.retry_delay(2), Duration::from_secs(10));
+        assert_eq!(timeout.retry_delay(3), Duration::from_secs(20));
+        assert_eq!(
+            SteamCmdError::RateLimited.retry_delay(1),
+            Duration::from_secs(60)
+        );
+    }
+
+    #[test]
+    fn parses_progress_states() {
+        let cases = [
+            (
+                " Update state (0x61) downloading, progress: 12.34 (123 / 456)",
+                0x61,
+                "downloading",
+                12.34,
+                123,
+                456,
+            ),
+            (
+                " Update state (0x5) verifying install, progress: 3.51 (1099511627 / 31336792143)",
+                0x5,
+                "verifying install",
+                3.51,
+                1099511627,
+                31336792143,
+            ),
+            (
+                " Update state (0x81) verifying update, progress: 99.00 (99 / 100)",
+                0x81,
+                "verifying update",
+                99.0,
+                99,
+                100,
+            ),
+            (
+                " Update state (0x101) committing, progress: 100.00 (30938473984 / 30938473984)",
+                0x101,
+                "committing",
+                100.0,
+                30938473984,
+                30938473984,
+            ),
+        ];
+        for (line, state_code, state, percent, bytes_done, bytes_total) in cases {
+            assert_eq!(
+                parse_progress(line),
+                Some(ProgressLine {
+                    state_code,
+                    state: state.to_string(),
+                    percent,
+                    bytes_done,
+                    bytes_total,
+                }),
+                "{}",
+                line
+            );
+        }
+    }
+
+    #[test]
+    fn progress_with_zero_total() {
+        let progress =
+            parse_progress(" Update state (0x3) reconfiguring, progress: 0.00 (0 / 0)").unwrap();
+        assert_eq!(progress.state, "reconfiguring");
+        assert_eq!(progress.percent, 0.0);
+        assert_eq!((progress.bytes_done, progress.bytes_total), (0, 0));
+    }
+
+    #[test]
+    fn rejects_malformed_progress() {
+        for line in [
+            "",
+            "Success! App '730' fully installed.",
+            " Update state (0xZZ) downloading, progress: 12.34 (123 / 456)",
+            " Update state (0x61) downloading, progress: abc (123 / 456)",
+            " Update state (0x61) downloading, progress: 12.34 (12x / 456)",
+            " Update state (0x61) downloading, progress: 12.34 (123 / -456)",
+            " Update state (0x61) downloading, progress: 12.34 (123 456)",
+            " Update state (0x61) downloading, progress: 12.34 (123 / 456",
+            " Update state (0x61) downloading progress: 12.34 (123 / 456)",
+            " Update state (0x61 downloading, progress: 12.34 (123 / 456)",
+        ] {
+            assert_eq!(parse_progress(line), None, "{:?}", line);
+        }
+    }
+
+    fn lines(lines: &[&str]) -> Vec<String> {
+        lines.iter().map(|line| line.to_string()).collect()
+    }
+}
@@ -0,0 +1,670 @@
+//! Valve KeyValues text format, as used by appmanifest_*.acf, libraryfolders.vdf,
+//! gameinfo.gi and workshop metadata.
+//!
+//! Documents keep their whitespace, comments and conditionals, so rendering an
+//! unmodified document gives back the input byte for byte, and edits only touch
+//! the values they change.
+
+use std::fmt;
+
+/// A parsed KeyValues document.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Document {
+    entries: Vec<Entry>,
+    /// Whitespace and comments after the last entry
+    trailing: String,
+}
+
+/// A `"key" "value"` pair or a `"key" { ... }` block. Keys may repeat.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Entry {
+    /// Whitespace and comments before the key
+    leading: String,
+    key: Token,
+    /// Everything between key and value, including a conditional like `[$WIN32]`
+    separator: String,
+    value: Value,
+    /// Conditional after a string value, with the whitespace before it
+    suffix: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Value {
+    String(Token),
+    Block {
+        entries: Vec<Entry>,
+        /// Whitespace and comments before the closing brace
+        closing: String,
+    },
+}
+
+/// A string as written in the source and as it reads after unescaping.
+#[derive(Debug, Clone, PartialEq)]
+struct Token {
+    raw: String,
+    text: String,
+}
+
+impl Token {
+    fn new(text: &str) -> Self {
+        Self {
+            raw: quote(text),
+            text: text.to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseError {
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}, column {}: {}", self.line, self.column, self.message)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+impl Document {
+    pub fn parse(text: &str) -> Result<Self, ParseError> {
+        let mut parser = Parser { src: text, pos: 0 };
+        let (entries, trailing) = parser.entries(false)?;
+        Ok(Self { entries, trailing })
+    }
+
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        for entry in &self.entries {
+            entry.render(&mut out);
+        }
+        out.push_str(&self.trailing);
+        out
+    }
+
+    /// First top-level entry with the given key, compared case-insensitively
+    /// like the engine does.
+    pub fn get(&self, key: &str) -> Option<&Entry> {
+        find(&self.entries, key)
+    }
+
+    /// Follows a path of keys from the top level, e.g. `["AppState", "buildid"]`.
+    pub fn get_path(&self, path: &[&str]) -> Option<&Entry> {
+        let (first, rest) = path.split_first()?;
+        rest.iter().try_fold(self.get(first)?, |entry, key| entry.get(key))
+    }
+
+    pub fn get_path_mut(&mut self, path: &[&str]) -> Option<&mut Entry> {
+        let (first, rest) = path.split_first()?;
+        let entry = find_mut(&mut self.entries, first)?;
+        rest.iter().try_fold(entry, |entry, key| entry.get_mut(key))
+    }
+}
+
+impl Entry {
+    /// A new `"key" "value"` pair, formatted the way Valve tools write it.
+    pub fn string(key: &str, value: &str) -> Self {
+        Self {
+            leading: String::new(),
+            key: Token::new(key),
+            separator: "\t\t".to_string(),
+            value: Value::String(Token::new(value)),
+            suffix: String::new(),
+        }
+    }
+
+    /// A new, empty `"key" { }` block.
+    pub fn block(key: &str) -> Self {
+        Self {
+            leading: String::new(),
+            key: Token::new(key),
+            separator: "\n".to_string(),
+            value: Value::Block {
+                entries: vec![],
+                closing: "\n".to_string(),
+            },
+            suffix: String::new(),
+        }
+    }
+
+    pub fn key(&self) -> &str {
+        &self.key.text
+    }
+
+    /// The value, if this is a string entry.
+    pub fn value(&self) -> Option<&str> {
+        match &self.value {
+            Value::String(token) => Some(&token.text),
+            Value::Block { .. } => None,
+        }
+    }
+
+    /// The child entries, if this is a block.
+    pub fn children(&self) -> Option<&[Entry]> {
+        match &self.value {
+            Value::Block { entries, .. } => Some(entries),
+            Value::String(_) => None,
+        }
+    }
+
+    /// First child with the given key.
+    pub fn get(&self, key: &str) -> Option<&Entry> {
+        find(self.children()?, key)
+    }
+
+    pub fn get_mut(&mut self, key: &str) -> Option<&mut Entry> {
+        match &mut self.value {
+            Value::Block { entries, .. } => find_mut(entries, key),
+            Value::String(_) => None,
+        }
+    }
+
+    /// Replaces the value, turning a block into a string entry if needed.
+    pub fn set_value(&mut self, value: &str) {
+        if let Value::String(token) = &self.value {
+            if token.text == value {
+                return;
+            }
+        }
+        if matches!(self.value, Value::Block { .. }) {
+            self.separator = "\t\t".to_string();
+        }
+        self.value = Value::String(Token::new(value));
+    }
+
+    /// Sets a string child, updating the first one with the key or appending
+    /// a new one. Does nothing if this is not a block.
+    pub fn set(&mut self, key: &str, value: &str) {
+        match self.get_mut(key) {
+            Some(entry) => entry.set_value(value),
+            None => self.insert(Entry::string(key, value)),
+        }
+    }
+
+    /// Appends a child, indented like its siblings. Does nothing if this is
+    /// not a block.
+    pub fn insert(&mut self, mut child: Entry) {
+        let indent = self.indent().to_string();
+        let Value::Block { entries, closing } = &mut self.value else {
+            return;
+        };
+        let child_indent = match entries.last() {
+            Some(sibling) => sibling.indent().to_string(),
+            None => format!("{}\t", indent),
+        };
+        child.leading = format!("\n{}", child_indent);
+        child.reindent(&child_indent);
+        entries.push(child);
+        // The closing brace goes on its own line at the block's indentation
+        if !closing.contains('\n') {
+            *closing = format!("\n{}", indent);
+        }
+    }
+
+    /// Indentation of the key, taken from the whitespace on its line.
+    fn indent(&self) -> &str {
+        match self.leading.rfind('\n') {
+            Some(newline) => &self.leading[newline + 1..],
+            None => &self.leading,
+        }
+    }
+
+    /// Lays out a freshly built block for the given indentation.
+    fn reindent(&mut self, indent: &str) {
+        if let Value::Block { entries, closing } = &mut self.value {
+            self.separator = format!("\n{}", indent);
+            *closing = format!("\n{}", indent);
+            let child_indent = format!("{}\t", indent);
+            for child in entries {
+                child.leading = format!("\n{}", child_indent);
+                child.reindent(&child_indent);
+            }
+        }
+    }
+
+    fn render(&self, out: &mut String) {
+        out.push_str(&self.leading);
+        out.push_str(&self.key.raw);
+        out.push_str(&self.separator);
+        match &self.value {
+            Value::String(token) => out.push_str(&token.raw),
+            Value::Block { entries, closing } => {
+                out.push('{');
+                for entry in entries {
+                    entry.render(out);
+                }
+                out.push_str(closing);
+                out.push('}');
+            }
+        }
+        out.push_str(&self.suffix);
+    }
+}
+
+fn find<'a>(entries: &'a [Entry], key: &str) -> Option<&'a Entry> {
+    entries
+        .iter()
+        .find(|entry| entry.key().eq_ignore_ascii_case(key))
+}
+
+fn find_mut<'a>(entries: &'a mut [Entry], key: &str) -> Option<&'a mut Entry> {
+    entries
+        .iter_mut()
+        .find(|entry| entry.key().eq_ignore_ascii_case(key))
+}
+
+/// Quotes a string, escaping the characters the engine unescapes.
+fn quote(text: &str) -> String {
+    let mut quoted = String::with_capacity(text.len() + 2);
+    quoted.push('"');
+    for c in text.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\t' => quoted.push_str("\\t"),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+struct Parser<'a> {
+    src: &'a str,
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    /// Parses entries up to the end of the input, or up to and including the
+    /// closing brace of a block. Returns the entries and the trivia after them.
+    fn entries(&mut self, in_block: bool) -> Result<(Vec<Entry>, String), ParseError> {
+        let mut entries = vec![];
+        loop {
+            let leading = self.trivia();
+            match self.peek() {
+                None if in_block => return Err(self.error("missing closing '}'")),
+                None => return Ok((entries, leading)),
+                Some('}') if in_block => {
+                    self.pos += 1;
+                    return Ok((entries, leading));
+                }
+                Some('}') => return Err(self.error("unexpected '}'")),
+                Some('{') => return Err(self.error("expected a key, found '{'")),
+                Some('[') => return Err(self.error("conditional without a key")),
+                Some(_) => {}
+            }
+            let key = self.string()?;
+
+            let mut separator = String::new();
+            loop {
+                separator.push_str(&self.trivia());
+                if self.peek() != Some('[') {
+                    break;
+                }
+                separator.push_str(&self.conditional()?);
+            }
+
+            let value = match self.peek() {
+                None => return Err(self.error("expected a value")),
+                Some('{') => {
+                    self.pos += 1;
+                    let (entries, closing) = self.entries(true)?;
+                    Value::Block { entries, closing }
+                }
+                Some('}') => return Err(self.error("expected a value, found '}'")),
+                Some(_) => Value::String(self.string()?),
+            };
+
+            // A conditional on the same line belongs to this entry
+            let mut suffix = String::new();
+            if matches!(value, Value::String(_)) {
+                let start = self.pos;
+                let gap = self.trivia();
+                if self.peek() == Some('[') && !gap.contains('\n') && !gap.contains("//") {
+                    suffix = gap + &self.conditional()?;
+                } else {
+                    self.pos = start;
+                }
+            }
+
+            entries.push(Entry {
+                leading,
+                key,
+                separator,
+                value,
+                suffix,
+            });
+        }
+    }
+
+    /// Whitespace and `//` comments, returned verbatim.
+    fn trivia(&mut self) -> String {
+        let start = self.pos;
+        loop {
+            let rest = &self.src[self.pos..];
+            if rest.starts_with("//") {
+                self.pos += rest.find('\n').unwrap_or(rest.len());
+            } else if let Some(c) = rest.chars().next().filter(|c| c.is_whitespace()) {
+                self.pos += c.len_utf8();
+            } else {
+                break;
+            }
+        }
+        self.src[start..self.pos].to_string()
+    }
+
+    fn conditional(&mut self) -> Result<String, ParseError> {
+        let rest = &self.src[self.pos..];
+        match rest.find(']') {
+            Some(end) if !rest[..end].contains('\n') => {
+                self.pos += end + 1;
+                Ok(rest[..=end].to_string())
+            }
+            _ => Err(self.error("unterminated conditional")),
+        }
+    }
+
+    fn string(&mut self) -> Result<Token, ParseError> {
+        let start = self.pos;
+        let rest = &self.src[self.pos..];
+        if let Some(quoted) = rest.strip_prefix('"') {
+            let mut text = String::new();
+            let mut chars = quoted.char_indices();
+            while let Some((i, c)) = chars.next() {
+                match c {
+                    '"' => {
+                        self.pos += i + 2;
+                        return Ok(Token {
+                            raw: self.src[start..self.pos].to_string(),
+                            text,
+                        });
+                    }
+                    '\\' => match chars.next() {
+                        Some((_, 'n')) => text.push('\n'),
+                        Some((_, 't')) => text.push('\t'),
+                        Some((_, c @ ('\\' | '"'))) => text.push(c),
+                        // Unknown escapes are kept as written, as in Windows paths
+                        Some((_, c)) => {
+                            text.push('\\');
+                            text.push(c);
+                        }
+                        None => break,
+                    },
+                    c => text.push(c),
+                }
+            }
+            return Err(self.error("unterminated quoted string"));
+        }
+
+        let end = rest
+            .find(|c: char| c.is_whitespace() || matches!(c, '"' | '{' | '}' | '['))
+            .unwrap_or(rest.len());
+        self.pos += end;
+        let text = rest[..end].to_string();
+        Ok(Token {
+            raw: text.clone(),
+            text,
+        })
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.src[self.pos..].chars().next()
+    }
+
+    fn error(&self, message: &str) -> ParseError {
+        let before = &self.src[..self.pos];
+        let line = before.matches('\n').count() + 1;
+        let column = before[before.rfind('\n').map_or(0, |i| i + 1)..].chars().count() + 1;
+        ParseError {
+            line,
+            column,
+            message: message.to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const APPMANIFEST: &str = include_str!("../tests/corpus/appmanifest_730.acf");
+    const GAMEINFO: &str = include_str!("../tests/corpus/gameinfo.gi");
+    const LIBRARYFOLDERS: &str = include_str!("../tests/corpus/libraryfolders.vdf");
+    const CORPUS: &[&str] = &[APPMANIFEST, GAMEINFO, LIBRARYFOLDERS];
+
+    /// Small deterministic generator, so failures reproduce from the seed.
+    struct Rng(u64);
+
+    impl Rng {
+        fn next(&mut self) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
+
+        fn below(&mut self, n: usize) -> usize {
+            (self.next() % n as u64) as usize
+        }
+
+        fn pick<'a>(&mut self, items: &[&'a str]) -> &'a str {
+            items[self.below(items.len())]
+        }
+    }
+
+    #[test]
+    fn corpus_round_trips() {
+        for content in CORPUS {
+            let document = Document::parse(content).unwrap();
+            assert_eq!(document.render(), *content);
+        }
+    }
+
+    #[test]
+    fn reads_appmanifest() {
+        let document = Document::parse(APPMANIFEST).unwrap();
+        let value = |path: &[&str]| document.get_path(path).and_then(Entry::value);
+        assert_eq!(value(&["AppState", "buildid"]), Some("16150437"));
+        assert_eq!(value(&["appstate", "BUILDID"]), Some("16150437"));
+        assert_eq!(
+            value(&["AppState", "UserConfig", "BetaKey"]),
+            Some("staging")
+        );
+        let depots = document
+            .get_path(&["AppState", "InstalledDepots"])
+            .and_then(Entry::children)
+            .unwrap();
+        let keys: Vec<&str> = depots.iter().map(Entry::key).collect();
+        assert_eq!(keys, ["2347771", "2347773"]);
+        assert_eq!(
+            depots[1].get("manifest").and_then(Entry::value),
+            Some("6483570929185376401")
+        );
+    }
+
+    #[test]
+    fn reads_gameinfo() {
+        let document = Document::parse(GAMEINFO).unwrap();
+        let value = |path: &[&str]| document.get_path(path).and_then(Entry::value);
+        assert_eq!(value(&["GameInfo", "type"]), Some("multiplayer_only"));
+        assert_eq!(value(&["GameInfo", "tonemapping"]), Some("1"));
+        let search_paths = document
+            .get_path(&["GameInfo", "FileSystem", "SearchPaths"])
+            .and_then(Entry::children)
+            .unwrap();
+        // Duplicate keys are all kept, in order
+        let games: Vec<&str> = search_paths
+            .iter()
+            .filter(|entry| entry.key() == "Game")
+            .filter_map(Entry::value)
+            .collect();
+        assert_eq!(games, ["csgo", "csgo_imported", "csgo_core", "core"]);
+        let layered: Vec<&str> = search_paths
+            .iter()
+            .filter(|entry| entry.key() == "LayeredGameRoot")
+            .filter_map(Entry::value)
+            .collect();
+        assert_eq!(
+            layered,
+            ["../game_otherplatforms/etc", "../game_otherplatforms/dxt"]
+        );
+    }
+
+    #[test]
+    fn reads_libraryfolders() {
+        let document = Document::parse(LIBRARYFOLDERS).unwrap();
+        let value = |path: &[&str]| document.get_path(path).and_then(Entry::value);
+        assert_eq!(
+            value(&["libraryfolders", "1", "path"]),
+            Some("D:\\SteamLibrary")
+        );
+        assert_eq!(
+            value(&["libraryfolders", "1", "label"]),
+            Some("Games \"fast\" disk")
+        );
+        assert_eq!(
+            value(&["libraryfolders", "1", "apps", "730"]),
+            Some("35193423872")
+        );
+    }
+
+    #[test]
+    fn edits_touch_only_changed_values() {
+        let mut document = Document::parse(APPMANIFEST).unwrap();
+        let state = document.get_path_mut(&["AppState"]).unwrap();
+        state.set("buildid", "16200000");
+        let depots = state.get_mut("InstalledDepots").unwrap();
+        depots.insert(Entry::block("2347779"));
+        depots.get_mut("2347779").unwrap().set("manifest", "1234");
+
+        let expected = APPMANIFEST
+            .replace("\"16150437\"", "\"16200000\"")
+            .replace(
+                "\t\t\t\"size\"\t\t\"33776193856\"\n\t\t}\n",
+                "\t\t\t\"size\"\t\t\"33776193856\"\n\t\t}\n\t\t\"2347779\"\n\t\t{\n\t\t\t\"manifest\"\t\t\"1234\"\n\t\t}\n",
+            );
+        assert_eq!(document.render(), expected);
+    }
+
+    #[test]
+    fn set_value_escapes() {
+        let mut document = Document::parse("\"a\"\n{\n\t\"path\"\t\t\"x\"\n}\n").unwrap();
+        let entry = document.get_path_mut(&["a", "path"]).unwrap();
+        entry.set_value("C:\\games\\\"cs2\"");
+        assert_eq!(
+            document.render(),
+            "\"a\"\n{\n\t\"path\"\t\t\"C:\\\\games\\\\\\\"cs2\\\"\"\n}\n"
+        );
+        let reparsed = Document::parse(&document.render()).unwrap();
+        assert_eq!(
+            reparsed.get_path(&["a", "path"]).and_then(Entry::value),
+            Some("C:\\games\\\"cs2\"")
+        );
+    }
+
+    #[test]
+    fn reports_error_positions() {
+        let error = Document::parse("\"a\"\n{\n\t\"b\" \"c\n").unwrap_err();
+        assert_eq!((error.line, error.column), (3, 6));
+        assert_eq!(error.message, "unterminated quoted string");
+
+        let error = Document::parse("\"a\"\n{\n\t\"b\" \"c\"\n").unwrap_err();
+        assert_eq!((error.line, error.column), (4, 1));
+        assert_eq!(error.message, "missing closing '}'");
+
+        let error = Document::parse("\"é\" }").unwrap_err();
+        assert_eq!((error.line, error.column), (1, 5));
+    }
+
+    fn generate_trivia(rng: &mut Rng, out: &mut String) {
+        out.push_str(rng.pick(&[" ", "\t", "\n", "\r\n", "\t\t"]));
+        for _ in 0..rng.below(3) {
+            out.push_str(rng.pick(&[
+                " ",
+                "\n\t",
+                "// comment\n",
+                "\t// \"quoted\" { } [$X]\n",
+                "\r\n",
+            ]));
+        }
+    }
+
+    fn generate_string(rng: &mut Rng, out: &mut String) {
+        if rng.below(4) == 0 {
+            out.push_str(rng.pick(&["key", "Game", "csgo_core", "1", "-3.5", "a.b/c"]));
+            return;
+        }
+        out.push('"');
+        for _ in 0..rng.below(6) {
+            out.push_str(rng.pick(&[
+                "a", "Z", "0", " ", "\\\"", "\\\\", "\\n", "\\t", "\\q", "é", "{", "}", "[", "//",
+            ]));
+        }
+        out.push('"');
+    }
+
+    fn generate_entries(rng: &mut Rng, depth: usize, out: &mut String) {
+        for _ in 0..rng.below(5) {
+            generate_trivia(rng, out);
+            generate_string(rng, out);
+            generate_trivia(rng, out);
+            if rng.below(6) == 0 {
+                out.push_str(rng.pick(&["[$WIN32]", "[!$X360 && $PS3]"]));
+                generate_trivia(rng, out);
+            }
+            if depth < 3 && rng.below(3) == 0 {
+                out.push('{');
+                generate_entries(rng, depth + 1, out);
+                generate_trivia(rng, out);
+                out.push('}');
+            } else {
+                generate_string(rng, out);
+                if rng.below(6) == 0 {
+                    out.push_str(rng.pick(&[" [$OSX]", "\t[!$LINUX]"]));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn generated_documents_round_trip() {
+        let mut rng = Rng(0x2545_f491_4f6c_dd1d);
+        for _ in 0..2000 {
+            let mut text = String::new();
+            generate_entries(&mut rng, 0, &mut text);
+            if rng.below(2) == 0 {
+                generate_trivia(&mut rng, &mut text);
+            }
+            let document = Document::parse(&text).unwrap_or_else(|e| panic!("{} in {:?}", e, text));
+            assert_eq!(document.render(), text);
+            assert_eq!(Document::parse(&document.render()).unwrap(), document);
+        }
+    }
+
+    #[test]
+    fn mutated_corpus_never_panics_and_round_trips_when_valid() {
+        let mut rng = Rng(0x9e37_79b9_7f4a_7c15);
+        for _ in 0..3000 {
+            let mut chars: Vec<char> = CORPUS[rng.below(CORPUS.len())].chars().collect();
+            for _ in 0..1 + rng.below(4) {
+                let at = rng.below(chars.len() + 1);
+                if rng.below(2) == 0 && at < chars.len() {
+                    let end = (at + 1 + rng.below(8)).min(chars.len());
+                    chars.drain(at..end);
+                } else {
+                    let inserted =
+                        rng.pick(&["\"", "{", "}", "[", "]", "\\", "/", "\n", " ", "x", "é"]);
+                    chars.splice(at..at, inserted.chars());
+                }
+            }
+            let text: String = chars.into_iter().collect();
+            if let Ok(document) = Document::parse(&text) {
+                assert_eq!(document.render(), text);
+            }
+        }
+    }
+}